@@ -1,40 +1,76 @@
-use rand::Rng;
-use std::collections::HashMap;
-use lazy_static::lazy_static;
-use std::f64;
-
-type Genome = Vec<u8>;
-
-const POPULATION_SIZE: usize = 10;
-const MUTATION_RATE: f64 = 0.01;
-
-const MAX_GENERATIONS: usize = 10;
-
-lazy_static! {
-    static ref GRAMMAR: HashMap<&'static str, Vec<&'static str>> = {
-        let mut map = HashMap::new();
-        map.insert("S", vec!["E"]);
-        map.insert("E", vec!["E + T", "E - T", "T"]);
-        map.insert("T", vec!["T * F", "T / F", "F"]);
-        map.insert("F", vec!["x", "y", "( E )"]);
-        map
-    };
+use gers_dev::engine::{Config, Engine, Problem};
+use gers_dev::evolutionary_ops::ParentSelection;
+use gers_dev::fitness::Direction;
+use gers_dev::grammar::Grammar;
+
+// Labels points (x, y) with 1 when the evolved expression is above 0.5 and 0 otherwise.
+struct Classification {
+    grammar: Grammar,
+    data: Vec<(f64, f64, i32)>,
 }
 
+impl Classification {
+    fn new() -> Self {
+        Classification {
+            grammar: "
+                <S> ::= <E>
+                <E> ::= <E> + <T> | <E> - <T> | <T>
+                <T> ::= <T> * <F> | <T> / <F> | <F>
+                <F> ::= x | y | ( <E> )
+            ".parse().unwrap(),
+            data: vec![
+                (0.5, 0.5, 1),
+                (0.2, 0.4, 0),
+                (0.7, 0.3, 0),
+                (0.9, 0.9, 1),
+            ],
+        }
+    }
+}
 
+impl Problem for Classification {
+    type Fitness = i32;
 
-fn evaluate_fitness(expression: &str, data: &[(f64, f64, i32)]) -> i32 {
-    let mut correct_classifications = 0;
+    fn grammar(&self) -> &Grammar {
+        &self.grammar
+    }
 
-    for (x, y, label) in data {
-        let result = evaluate_expression(expression, *x, *y);
-        let predicted_label = if result > 0.5 { 1 } else { 0 };
-        if predicted_label == *label {
-            correct_classifications += 1;
-        }
+    fn direction(&self) -> Direction {
+        Direction::Maximize
+    }
+
+    fn evaluate(&self, phenotype: &str) -> i32 {
+        evaluate_fitness(phenotype, &self.data)
+    }
+
+    // Invalid individuals are treated as the empty expression, which evaluates to 0.
+    fn invalid_fitness(&self) -> i32 {
+        evaluate_fitness("", &self.data)
+    }
+
+    fn evaluate_cases(&self, phenotype: &str) -> Option<Vec<f64>> {
+        Some(case_errors(phenotype, &self.data))
     }
 
-    correct_classifications
+    fn is_solved(&self, fitness: &i32) -> bool {
+        *fitness == self.data.len() as i32
+    }
+}
+
+
+fn evaluate_fitness(expression: &str, data: &[(f64, f64, i32)]) -> i32 {
+    case_errors(expression, data).iter().filter(|&&error| error == 0.0).count() as i32
+}
+
+// 0 for each point labelled correctly and 1 for each one that is not.
+fn case_errors(expression: &str, data: &[(f64, f64, i32)]) -> Vec<f64> {
+    data.iter()
+        .map(|(x, y, label)| {
+            let result = evaluate_expression(expression, *x, *y);
+            let predicted_label = if result > 0.5 { 1 } else { 0 };
+            if predicted_label == *label { 0.0 } else { 1.0 }
+        })
+        .collect()
 }
 
 fn evaluate_expression(expression: &str, x_val: f64, y_val: f64) -> f64 {
@@ -42,17 +78,17 @@ fn evaluate_expression(expression: &str, x_val: f64, y_val: f64) -> f64 {
 
     let mut index = 0;
 
-    fn parse_E(tokens: &[&str], x_val: f64, y_val: f64, index: &mut usize) -> f64 {
-        let mut value = parse_T(tokens, x_val, y_val, index);
+    fn parse_e(tokens: &[&str], x_val: f64, y_val: f64, index: &mut usize) -> f64 {
+        let mut value = parse_t(tokens, x_val, y_val, index);
         while *index < tokens.len() {
             match tokens.get(*index) {
                 Some(&"+") => {
                     *index += 1;
-                    value += parse_T(tokens, x_val, y_val, index);
+                    value += parse_t(tokens, x_val, y_val, index);
                 }
                 Some(&"-") => {
                     *index += 1;
-                    value -= parse_T(tokens, x_val, y_val, index);
+                    value -= parse_t(tokens, x_val, y_val, index);
                 }
                 _ => break,
             }
@@ -60,17 +96,17 @@ fn evaluate_expression(expression: &str, x_val: f64, y_val: f64) -> f64 {
         value
     }
 
-    fn parse_T(tokens: &[&str], x_val: f64, y_val: f64, index: &mut usize) -> f64 {
-        let mut value = parse_F(tokens, x_val, y_val, index);
+    fn parse_t(tokens: &[&str], x_val: f64, y_val: f64, index: &mut usize) -> f64 {
+        let mut value = parse_f(tokens, x_val, y_val, index);
         while *index < tokens.len() {
             match tokens.get(*index) {
                 Some(&"*") => {
                     *index += 1;
-                    value *= parse_F(tokens, x_val, y_val, index);
+                    value *= parse_f(tokens, x_val, y_val, index);
                 }
                 Some(&"/") => {
                     *index += 1;
-                    value /= parse_F(tokens, x_val, y_val, index);
+                    value /= parse_f(tokens, x_val, y_val, index);
                 }
                 _ => break,
             }
@@ -78,7 +114,7 @@ fn evaluate_expression(expression: &str, x_val: f64, y_val: f64) -> f64 {
         value
     }
 
-    fn parse_F(tokens: &[&str], x_val: f64, y_val: f64, index: &mut usize) -> f64 {
+    fn parse_f(tokens: &[&str], x_val: f64, y_val: f64, index: &mut usize) -> f64 {
         let value;
         match tokens.get(*index) {
             Some(&"x") => {
@@ -91,7 +127,7 @@ fn evaluate_expression(expression: &str, x_val: f64, y_val: f64) -> f64 {
             }
             Some(&"(") => {
                 *index += 1;
-                value = parse_E(tokens, x_val, y_val, index);
+                value = parse_e(tokens, x_val, y_val, index);
                 if let Some(&")") = tokens.get(*index) {
                     *index += 1;
                 }
@@ -107,96 +143,24 @@ fn evaluate_expression(expression: &str, x_val: f64, y_val: f64) -> f64 {
         value
     }
 
-    parse_E(&tokens, x_val, y_val, &mut index)
-}
-
-fn evolve_population(population: &[Genome]) -> Vec<Genome> {
-    let mut new_population = Vec::with_capacity(POPULATION_SIZE);
-
-    for _ in 0..POPULATION_SIZE {
-        let mut child = population[rand::thread_rng().gen_range(0..POPULATION_SIZE)].clone();
-        if rand::thread_rng().gen_bool(MUTATION_RATE) {
-            mutate(&mut child);
-        }
-        new_population.push(child);
-    }
-
-    new_population
-}
-
-fn mutate(genome: &mut Genome) {
-    let index = rand::thread_rng().gen_range(0..genome.len());
-    genome[index] = rand::thread_rng().gen_range(0..255);
-}
-
-fn map_genome_to_phenotype(genome: &Genome) -> String {
-    let mut output = String::new();
-    let mut symbols = vec!["S"];
-    let mut genome_index = 0;
-    let mut iteration_count = 0;
-    const MAX_ITERATIONS: usize = 1000;
-
-    while let Some(top) = symbols.pop() {
-        if iteration_count > MAX_ITERATIONS {
-            break;
-        }
-        if let Some(productions) = GRAMMAR.get(top) {
-            let gene = genome[genome_index % genome.len()];  // Cyclically use the genome
-            let production = productions[gene as usize % productions.len()];
-            for symbol in production.split_whitespace().rev() {
-                symbols.push(symbol);
-            }
-            genome_index += 1;  // Move to the next gene in the genome
-        } else {
-            output.push_str(top);
-            output.push(' ');
-        }
-        iteration_count += 1;
-    }
-
-    output.trim().to_string()
+    parse_e(&tokens, x_val, y_val, &mut index)
 }
 
 fn main() {
-
-    let mut population: Vec<Genome> = (0..POPULATION_SIZE)
-        .map(|_| {
-            (0..10).map(|_| rand::thread_rng().gen_range(0..255)).collect()
-        })
-        .collect();
-
-    let mut best_genome: Option<Genome> = None;
-    let mut best_fitness: i32 = 0;
-
-    println!("pop: {:?}", population);
-
-    let data = vec![
-    (0.5, 0.5, 1),
-    (0.2, 0.4, 0),
-    (0.7, 0.3, 0),
-    (0.9, 0.9, 1),
-    ];
-
-    for generation in 0..MAX_GENERATIONS {
-        population = evolve_population(&population);
-
-        if let Some(current_best_genome) = population.iter().max_by_key(|genome| evaluate_fitness(&map_genome_to_phenotype(genome), &data)) {
-            let current_best_fitness = evaluate_fitness(&map_genome_to_phenotype(current_best_genome), &data);
-            println!("Generation {}: Best Genome (Fitness = {}): {:?}", generation, evaluate_fitness(&map_genome_to_phenotype(current_best_genome), &data), current_best_genome);
-            if current_best_fitness > best_fitness {
-                best_fitness = current_best_fitness;
-                best_genome = Some(current_best_genome.clone());
-            }
-        }
-    }
-
-    if let Some(best) = best_genome {
-        println!("Best Individual: {} Genome: {:?}", map_genome_to_phenotype(&best), &best);
-        println!("Fitness: {}", best_fitness);
-        let new_data_point = (0.9, 0.9);
-        let predicted_value = evaluate_expression(&map_genome_to_phenotype(&best), new_data_point.0, new_data_point.1);
-        println!("pred: {}", predicted_value);
-    } else {
-        println!("No best individual found.");
-    }
-}
\ No newline at end of file
+    let config = Config {
+        population_size: 10,
+        generations: 10,
+        selection: ParentSelection::Lexicase,
+        verbose: true,
+        ..Config::default()
+    };
+    let result = Engine::new(Classification::new(), config).run();
+
+    let best = result.best;
+    let expression = best.phenotype.unwrap();
+    println!("Best Individual: {} Genome: {:?}", expression, best.genome);
+    println!("Fitness: {}", best.fitness.unwrap());
+    let new_data_point = (0.9, 0.9);
+    let predicted_value = evaluate_expression(&expression, new_data_point.0, new_data_point.1);
+    println!("pred: {}", predicted_value);
+}
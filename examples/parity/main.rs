@@ -1,51 +1,64 @@
-use rand::Rng;
-use lazy_static::lazy_static;
-use std::collections::HashMap;
-
-
-// Genome Representation
-type Genome = Vec<u8>;
-
-// Grammar Representation
-// For simplicity the grammar is saved as a hashmap and not read from a grammar file.
-lazy_static! {
-    static ref GRAMMAR: HashMap<&'static str, Vec<&'static str>> = {
-        let mut map = HashMap::new();
-        map.insert("S", vec!["E"]);
-        map.insert("E", vec!["E OR T", "T"]);
-        map.insert("T", vec!["T AND F", "F"]);
-        map.insert("F", vec!["NOT F", "A", "B", "C"]);
-        map
-    };
-}
+use gers_dev::engine::{Config, Engine, Problem};
+use gers_dev::evolutionary_ops::ParentSelection;
+use gers_dev::fitness::Direction;
+use gers_dev::grammar::Grammar;
 
 
-// Genome-to-Phenotype Mapping
-fn map_genome_to_phenotype(genome: &Genome) -> String {
-    let mut output = String::new();
-    let mut symbols = vec!["S"];
-    let mut genome_index = 0;
+// Three-input even parity. For simplicity the grammar is kept in the source and not read
+// from a grammar file.
+struct Parity {
+    grammar: Grammar,
+}
 
-    while let Some(top) = symbols.pop() {
-        if let Some(productions) = GRAMMAR.get(top) {
-            let gene = genome[genome_index % genome.len()];  // Cyclically use the genome
-            let production = productions[gene as usize % productions.len()];
-            for symbol in production.split_whitespace().rev() {
-                symbols.push(symbol);
-            }
-            genome_index += 1;  // Move to the next gene in the genome
-        } else {
-            output.push_str(top);
-            output.push(' ');
+impl Parity {
+    fn new() -> Self {
+        Parity {
+            grammar: "
+                <S> ::= <E>
+                <E> ::= <E> OR <T> | <T>
+                <T> ::= <T> AND <F> | <F>
+                <F> ::= NOT <F> | A | B | C
+            ".parse().unwrap(),
         }
     }
+}
+
+impl Problem for Parity {
+    type Fitness = i32;
 
-    output.trim().to_string()
+    fn grammar(&self) -> &Grammar {
+        &self.grammar
+    }
+
+    fn direction(&self) -> Direction {
+        Direction::Maximize
+    }
+
+    fn evaluate(&self, phenotype: &str) -> i32 {
+        evaluate_fitness(phenotype)
+    }
+
+    fn invalid_fitness(&self) -> i32 {
+        0
+    }
+
+    fn evaluate_cases(&self, phenotype: &str) -> Option<Vec<f64>> {
+        Some(case_errors(phenotype))
+    }
+
+    fn is_solved(&self, fitness: &i32) -> bool {
+        *fitness == 8
+    }
 }
 
 
-// Fitness Evaluation
+// Fitness Evaluation: the number of input combinations classified correctly
 fn evaluate_fitness(expression: &str) -> i32 {
+    case_errors(expression).iter().filter(|&&error| error == 0.0).count() as i32
+}
+
+// 0 for each input combination classified correctly and 1 for each one that is not
+fn case_errors(expression: &str) -> Vec<f64> {
     let combinations = [
         (false, false, false),
         (false, false, true),
@@ -57,16 +70,10 @@ fn evaluate_fitness(expression: &str) -> i32 {
         (true, true, true),
     ];
 
-    let mut correct_count = 0;
-
-    for (a, b, c) in &combinations {
-        let result = evaluate_expression(expression, *a, *b, *c);
-        if result == (*a ^ *b ^ *c) {
-            correct_count += 1;
-        }
-    }
-
-    correct_count
+    combinations
+        .iter()
+        .map(|&(a, b, c)| if evaluate_expression(expression, a, b, c) == (a ^ b ^ c) { 0.0 } else { 1.0 })
+        .collect()
 }
 
 // evaluate the Boolean expression
@@ -78,14 +85,8 @@ fn evaluate_expression(expression: &str, a: bool, b: bool, c: bool) -> bool {
     let postfix = infix_to_postfix(&tokens);
 
     // Evaluation
-    match evaluate_postfix(&postfix, a, b, c) {
-        Some(result) => result,  // Handle the Some(bool) case
-        None => {
-            // Handle the None case
-            // eprintln!("Error: Malformed postfix expression or evaluation error.");
-            false
-        }
-    }
+    // A malformed postfix expression evaluates to false
+    evaluate_postfix(&postfix, a, b, c).unwrap_or_default()
 }
 
 fn infix_to_postfix<'a>(tokens: &'a [&'a str]) -> Vec<&'a str> {
@@ -172,65 +173,20 @@ fn evaluate_postfix(postfix: &[&str], a: bool, b: bool, c: bool) -> Option<bool>
 }
 
 
-// Evolutionary Operations
-fn mutate(genome: &mut Genome) {
-    let index = rand::thread_rng().gen_range(0..genome.len());
-    genome[index] = rand::thread_rng().gen_range(0..255);
-}
-
-// Population Management
-const POPULATION_SIZE: usize = 10;
-const MUTATION_RATE: f64 = 0.01;
-
-fn evolve_population(population: &[Genome]) -> Vec<Genome> {
-    let mut new_population = Vec::with_capacity(POPULATION_SIZE);
-
-    for _ in 0..POPULATION_SIZE {
-        let mut child = population[rand::thread_rng().gen_range(0..POPULATION_SIZE)].clone();
-        if rand::thread_rng().gen_bool(MUTATION_RATE) {
-            mutate(&mut child);
-        }
-        new_population.push(child);
-    }
-
-    new_population
-}
-
-// Termination Criteria
-const MAX_GENERATIONS: usize = 10;
-
 fn main() {
-    // Initialize population
-    let mut population: Vec<Genome> = (0..POPULATION_SIZE)
-        .map(|_| {
-            (0..10).map(|_| rand::thread_rng().gen_range(0..255)).collect()
-        })
-        .collect();
-
-    let mut best_genome: Option<Genome> = None;
-    let mut best_fitness = 0;
-
-    for generation in 0..MAX_GENERATIONS {
-        population = evolve_population(&population);
-
-        // Find the best genome of this generation
-        if let Some(current_best_genome) = population.iter().max_by_key(|genome| evaluate_fitness(&map_genome_to_phenotype(genome))) {
-            let current_best_fitness = evaluate_fitness(&map_genome_to_phenotype(current_best_genome));
-            println!("Generation {}: Best Genome (Fitness = {}): {:?}", generation, evaluate_fitness(&map_genome_to_phenotype(current_best_genome)), current_best_genome);
-            if current_best_fitness > best_fitness {
-                best_fitness = current_best_fitness;
-                best_genome = Some(current_best_genome.clone());
-            }
-        }
-    }
+    let config = Config {
+        population_size: 10,
+        generations: 10,
+        selection: ParentSelection::Lexicase,
+        verbose: true,
+        ..Config::default()
+    };
+    let result = Engine::new(Parity::new(), config).run();
 
     // Print the best individual at the end of the run
-    if let Some(best) = best_genome {
-        println!("Best Individual: {} Genome: {:?}", map_genome_to_phenotype(&best), &best);
-        println!("Fitness: {}", best_fitness);
-    } else {
-        println!("No best individual found.");
-    }
+    let best = result.best;
+    println!("Best Individual: {} Genome: {:?}", best.phenotype.unwrap(), best.genome);
+    println!("Fitness: {}", best.fitness.unwrap());
 }
 
 #[cfg(test)]
@@ -261,7 +217,7 @@ mod tests {
     #[test]
     fn test_map_genome_to_phenotype() {
         let genome = vec![0, 1, 2, 3, 4];
-        let phenotype = map_genome_to_phenotype(&genome);
+        let phenotype = gers_dev::mapper::Mapper::default().map(Parity::new().grammar(), &genome).phenotype;
         println!("{}", phenotype);
         assert_eq!(phenotype, "NOT NOT A AND B");
     }
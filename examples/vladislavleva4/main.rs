@@ -1,8 +1,18 @@
 use rand::Rng;
-use gers_dev::{grammar, population_mgmt, genome};
+use gers_dev::bloat::{BloatControl, Parsimony};
+use gers_dev::engine::{Config, Engine, InitialPopulation, Problem};
+use gers_dev::fitness::{Direction, MultiObjective};
+use gers_dev::grammar::{self, Grammar};
+use gers_dev::individual::Individual;
+use gers_dev::mapper::Mapper;
+use gers_dev::population_mgmt::{export_front, pareto_front, Replacement};
+use gers_dev::random;
 
 use std::fs::File;
-use std::io::{self, BufReader, BufWriter, Write, BufRead};
+use std::io::{BufWriter, Write};
+
+// Fitness of expressions that fail to parse, including invalid individuals.
+const INVALID_FITNESS: f64 = 10000.0;
 
 #[derive(Debug, Clone)]
 enum Expr {
@@ -84,7 +94,7 @@ fn evaluate(expr: &Expr, inputs: &[f64; 5]) -> f64 {
             if divisor != 0.0 {
                 evaluate(a, inputs) / divisor
             } else {
-                std::f64::NAN
+                f64::NAN
             }
         }
         Expr::Pow(a, b) => evaluate(a, inputs).powf(evaluate(b, inputs)),
@@ -98,8 +108,7 @@ fn vladislavleva4(x: &[f64; 5]) -> f64 {
     10.0 / (5.0 + (x[0]-3.0).powi(2) + (x[1]-3.0).powi(2) + (x[2]-3.0).powi(2) + (x[3]-3.0).powi(2) + (x[4]-3.0).powi(2))
 }
 
-fn generate_dataset(samples: usize, range: (f64, f64)) -> Vec<([f64; 5], f64)> {
-    let mut rng = rand::thread_rng();
+fn generate_dataset(samples: usize, range: (f64, f64), rng: &mut impl Rng) -> Vec<([f64; 5], f64)> {
     let mut dataset = Vec::with_capacity(samples);
 
     for _ in 0..samples {
@@ -117,197 +126,166 @@ fn generate_dataset(samples: usize, range: (f64, f64)) -> Vec<([f64; 5], f64)> {
     dataset
 }
 
-fn tournament_selection<'a>(population: &'a Vec<genome::Genome>, fitness_values: &Vec<f64>, tournament_size: usize) -> &'a genome::Genome {
-    let mut best_individual = &population[rand::random::<usize>() % population.len()];
-    let mut best_fitness = f64::MAX; // best_fitness is MAX because we are minimizing fitness
-
-    for _ in 0..tournament_size {
-        let index = rand::random::<usize>() % population.len();
-        if fitness_values[index] < best_fitness {
-            best_fitness = fitness_values[index];
-            best_individual = &population[index];
-        }
-    }
-
-    best_individual
+fn evaluate_fitness(expression: &str, data: &[([f64; 5], f64)]) -> f64 {
+    // The expression is parsed once and then evaluated on every sample.
+    let expr = match parse_expression(expression) {
+        Ok(expr) => expr,
+        Err(_e) => return INVALID_FITNESS, // return max fitness for invalid expression
+    };
+
+    let total_error: f64 = data
+        .iter()
+        .map(|(x, y)| {
+            let error = evaluate(&expr, x) - y;
+            error * error
+        })
+        .sum();
+
+    total_error / data.len() as f64
 }
 
+fn save_dataset_to_file(filename: &str, data: &[([f64; 5], f64)]) {
+    let file = File::create(filename).unwrap();
+    let mut writer = BufWriter::new(file);
 
-fn mutate(individual: &mut Vec<usize>, max_gene_value: usize) {
-    let mutation_point = rand::random::<usize>() % individual.len();
-    individual[mutation_point] = rand::random::<usize>() % max_gene_value;
+    for &(inputs, output) in data {
+        writeln!(writer, "{},{},{},{},{},{}", inputs[0], inputs[1], inputs[2], inputs[3], inputs[4], output).unwrap();
+    }
 }
 
-fn one_point_crossover(parent1: &Vec<usize>, parent2: &Vec<usize>) -> (Vec<usize>, Vec<usize>) {
-    let crossover_point = rand::random::<usize>() % parent1.len();
-    let mut offspring1 = parent1[..crossover_point].to_vec();
-    offspring1.extend(&parent2[crossover_point..]);
-    let mut offspring2 = parent2[..crossover_point].to_vec();
-    offspring2.extend(&parent1[crossover_point..]);
-
-    (offspring1, offspring2)
+// Mean squared error on data sampled from the Vladislavleva-4 function, minimised.
+struct Vladislavleva4 {
+    grammar: Grammar,
+    training_data: Vec<([f64; 5], f64)>,
+    test_data: Vec<([f64; 5], f64)>,
 }
 
-fn map_genome_to_expression(genome: &genome::Genome, grammar: &grammar::Grammar1) -> String {
-    let mut expression = String::new();
-    map_rule_to_expression(&mut expression, "Expr", genome, 0, grammar);
-    expression
-}
+impl Problem for Vladislavleva4 {
+    type Fitness = f64;
 
-fn map_rule_to_expression(expression: &mut String, rule: &str, genome: &[usize], index: usize, grammar: &grammar::Grammar1) -> usize {
-    if index >= genome.len() {
-        return index;
+    fn grammar(&self) -> &Grammar {
+        &self.grammar
     }
 
-    if let Some(expansions) = grammar.get(rule) {
-        let choice = genome[index] % expansions.len();
-        let selected_expansion = &expansions[choice];
-        let mut next_index = index + 1;
-        for part in selected_expansion.iter() {
-            if grammar.contains_key(part) {
-                next_index = map_rule_to_expression(expression, part, genome, next_index, grammar);
-            } else {
-                expression.push_str(&part);
-                expression.push(' ');
-            }
-        }
-        next_index
-    } else {
-        expression.push_str(rule);
-        expression.push(' ');
-        index + 1
+    fn direction(&self) -> Direction {
+        Direction::Minimize
     }
-}
-
-fn evaluate_fitness(expression: &str, data: &Vec<([f64; 5], f64)>) -> f64 {
-    // println!("The expression: {:?}", expression);
-    fn calculate_mse(expression: &str, data: &Vec<([f64; 5], f64)>) -> f64 {
-        let mut total_error = 0.0;
-
-        for (x, y) in data {
-            // println!("x: {} y: {}", x, y);
-            // let predicted_value = evaluate_expression(expression, x);
-            match parse_expression(expression) {
-                Ok(v) => {
-                    let predicted_value = evaluate(&v, x);
-                    // let pred = predicted_value.unwrap();
-                    let error = predicted_value - y;
-                    total_error += error * error;
-                }
-                Err(_e) => {
-                    // return f64::MAX;
-                    return 10000.0; // return max fitness for invalid expression
-                } 
-            };
-        }
 
-        let mse = total_error / data.len() as f64;
-        mse
+    fn evaluate(&self, phenotype: &str) -> f64 {
+        evaluate_fitness(phenotype, &self.training_data)
     }
 
-    let mse = calculate_mse(expression, data);
-    mse
-    
-}
-
-fn save_dataset_to_file(filename: &str, data: &Vec<([f64; 5], f64)>) {
-    let file = File::create(filename).unwrap();
-    let mut writer = BufWriter::new(file);
-
-    for &(inputs, output) in data {
-        writeln!(writer, "{},{},{},{},{},{}", inputs[0], inputs[1], inputs[2], inputs[3], inputs[4], output).unwrap();
+    fn invalid_fitness(&self) -> f64 {
+        INVALID_FITNESS
     }
-}
 
-fn read_dataset_from_file(filename: &str) -> io::Result<Vec<([f64; 5], f64)>> {
-    let file = File::open(filename)?;
-    let reader = BufReader::new(file);
-    let mut dataset = Vec::new();
-
-    for line in reader.lines() {
-        let line = line?;
-        let values: Vec<f64> = line.split(',').filter_map(|s| s.parse().ok()).collect();
-        if values.len() == 6 {
-            let inputs = [values[0], values[1], values[2], values[3], values[4]];
-            let output = values[5];
-            dataset.push((inputs, output));
-        }
+    fn evaluate_test(&self, phenotype: &str) -> Option<f64> {
+        Some(evaluate_fitness(phenotype, &self.test_data))
     }
-
-    Ok(dataset)
 }
 
+// The same problem with the number of codons used as a second objective, so that NSGA-II
+// finds a front of trade-offs between error and size.
+struct Vladislavleva4Pareto(Vladislavleva4);
 
-fn run_algorithm(grammar: &grammar::Grammar1) -> (f64, f64, String) {
+impl Problem for Vladislavleva4Pareto {
+    type Fitness = MultiObjective;
 
-    //parameters
-    let population_size = 100;
-    let max_genome_length = 100;
-    let mutation_probability = 0.01;
-    let crossover_probability = 0.9;
-    let max_generations = 20;
-    let tournament_size = 3;
-    let max_gene_value = 255;
+    fn grammar(&self) -> &Grammar {
+        &self.0.grammar
+    }
 
-    let mut population: Vec<genome::Genome> = population_mgmt::random_initialization(population_size, max_genome_length, max_gene_value);
-    let mut fitness_values = vec![0.0; population_size];
+    fn direction(&self) -> Direction {
+        Direction::Minimize
+    }
 
-    let training_data = generate_dataset(1024, (0.05, 6.05));
-    let test_data = generate_dataset(5000, (-0.25, 6.35));
+    fn evaluate(&self, phenotype: &str) -> MultiObjective {
+        MultiObjective(vec![self.0.evaluate(phenotype), 0.0])
+    }
 
-    save_dataset_to_file("vlad_train.txt", &training_data);
-    save_dataset_to_file("vlad_test.txt", &test_data);
+    fn evaluate_individual(&self, individual: &Individual<MultiObjective>) -> MultiObjective {
+        let error = self.0.evaluate(individual.phenotype.as_ref().unwrap());
+        MultiObjective(vec![error, individual.used_codons as f64])
+    }
 
-    for generation in 0..max_generations {
-        // Evaluate fitness of each individual in the population
-        for (i, individual) in population.iter().enumerate() {
-            fitness_values[i] = evaluate_fitness(&map_genome_to_expression(individual, &grammar), &training_data);
-        }
+    fn invalid_fitness(&self) -> MultiObjective {
+        MultiObjective(vec![INVALID_FITNESS, f64::INFINITY])
+    }
+}
 
-        let current_best_index = fitness_values.iter().enumerate().min_by(|a, b| a.1.partial_cmp(b.1).unwrap()).unwrap().0;
-        let current_best_fitness = fitness_values[current_best_index];
-        let current_best_expression = map_genome_to_expression(&population[current_best_index], &grammar);
-        println!("Generation {}: Best Genome (Fitness = {}): {:?}", generation, current_best_fitness, current_best_expression);
-    
-        let mut new_population: Vec<genome::Genome> = Vec::new();
-    
-        while new_population.len() < population_size {
-            let parent1 = tournament_selection(&population, &fitness_values, tournament_size);
-            let parent2 = tournament_selection(&population, &fitness_values, tournament_size);
-    
-            if rand::random::<f64>() < crossover_probability {
-                let (child1, child2) = one_point_crossover(parent1, parent2);
-                new_population.push(child1);
-                new_population.push(child2);
-            } else {
-                new_population.push(parent1.clone());
-                new_population.push(parent2.clone());
-            }
-        }
-    
-        for individual in new_population.iter_mut() {
-            if rand::random::<f64>() < mutation_probability {
-                mutate(individual, max_gene_value);
-            }
-        }
-    
-        population = new_population;
+// One NSGA-II run; the final front is printed and written to `vlad_front.csv`.
+fn run_pareto(grammar: &Grammar, seed: u64) {
+    let mut data_rng = random::stream(seed, 1);
+    let training_data = generate_dataset(1024, (0.05, 6.05), &mut data_rng);
+    let test_data = generate_dataset(5000, (-0.25, 6.35), &mut data_rng);
+
+    let problem = Vladislavleva4Pareto(Vladislavleva4 { grammar: grammar.clone(), training_data, test_data });
+    let config = Config {
+        population_size: 100,
+        generations: 20,
+        initial_population: InitialPopulation::Random { length: 100 },
+        mapper: Mapper { max_wraps: 0, ..Mapper::default() },
+        replacement: Replacement::Nsga2,
+        mutation_probability: 0.01,
+        seed: Some(seed),
+        ..Config::default()
+    };
+    let result = Engine::new(problem, config).run();
+
+    let front = pareto_front(&result.final_population, Direction::Minimize);
+    for individual in &front {
+        println!("{} {}", individual.fitness.as_ref().unwrap(), individual.phenotype.as_ref().unwrap());
     }
+    let mut writer = BufWriter::new(File::create("vlad_front.csv").unwrap());
+    export_front(&front, &mut writer).unwrap();
+}
 
-    let best_index = fitness_values.iter().enumerate().min_by(|a, b| a.1.partial_cmp(b.1).unwrap()).unwrap().0;
-    let best_fitness = fitness_values[best_index];
-    let best_expression = map_genome_to_expression(&population[best_index], &grammar);
+// Returns the best training fitness, the final population's average fitness, the best
+// expression and its test fitness. Each run uses two streams of `seed`: one for its data and
+// one for the evolution.
+fn run_algorithm(grammar: &Grammar, seed: u64, run: u64) -> (f64, f64, String, Option<f64>) {
+    let mut data_rng = random::stream(seed, 2 * run + 1);
+    let training_data = generate_dataset(1024, (0.05, 6.05), &mut data_rng);
+    let test_data = generate_dataset(5000, (-0.25, 6.35), &mut data_rng);
 
-    // Calculate average fitness of the population
-    let avg_fitness: f64 = fitness_values.iter().sum::<f64>() / population_size as f64;
+    save_dataset_to_file("vlad_train.txt", &training_data);
+    save_dataset_to_file("vlad_test.txt", &test_data);
 
-    (best_fitness, avg_fitness, best_expression)
+    let problem = Vladislavleva4 { grammar: grammar.clone(), training_data, test_data };
+    let config = Config {
+        population_size: 100,
+        generations: 20,
+        initial_population: InitialPopulation::Random { length: 100 },
+        // The genome is not wrapped.
+        mapper: Mapper { max_wraps: 0, ..Mapper::default() },
+        tournament_size: 3,
+        // Prefer smaller expressions among parents of similar fitness.
+        bloat_control: BloatControl { parsimony: Parsimony::DoubleTournament { size_pressure: 1.4 }, ..BloatControl::default() },
+        crossover_probability: 0.9,
+        mutation_probability: 0.01,
+        verbose: true,
+        seed: Some(seed),
+        ..Config::default()
+    };
+    let result = Engine::new(problem, config).run_stream(2 * run);
+
+    let population = &result.final_population;
+    let avg_fitness = population.iter().map(|individual| individual.fitness.unwrap()).sum::<f64>() / population.len() as f64;
+    (result.best.fitness.unwrap(), avg_fitness, result.best.phenotype.unwrap(), result.test_fitness)
 }
 
 
 fn main() {
-    let grammar_filename = ".\\grammars\\vlad2.bnf";
+    let grammar_filename = "grammars/vlad2.bnf";
     let num_runs = 5; // Number of runs
+    // Pass a seed to repeat an experiment, and `--pareto` for a multi-objective run.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let pareto = args.iter().any(|arg| arg == "--pareto");
+    let seed = args
+        .iter()
+        .find(|arg| !arg.starts_with("--"))
+        .map_or_else(random::random_seed, |seed| seed.parse().expect("the seed is a number"));
+    println!("seed: {}", seed);
 
     let mut best_fitnesses = Vec::new();
     let mut average_fitnesses = Vec::new();
@@ -315,45 +293,33 @@ fn main() {
 
     match grammar::read_grammar_from_file(grammar_filename) {
         Ok(grammar) => {
-            println!("grammar: {:?}", grammar);
-            for _ in 0..num_runs {
-                let (best_fitness, avg_fitness, best_expr) = run_algorithm(&grammar);
+            println!("grammar:\n{}", grammar);
+            if pareto {
+                run_pareto(&grammar, seed);
+                return;
+            }
+            for run in 0..num_runs {
+                let (best_fitness, avg_fitness, best_expr, test_fitness) = run_algorithm(&grammar, seed, run);
                 best_fitnesses.push(best_fitness);
                 average_fitnesses.push(avg_fitness);
-                best_expressions.push(best_expr);
+                best_expressions.push((best_expr, test_fitness));
             }
 
             println!("best fitnesses: {:?}", best_fitnesses);
 
             // Analyze results
-            // let overall_best_fitness = best_fitnesses.iter().cloned().fold(0./0., f64::max);
             let overall_best_fitness = best_fitnesses.iter().cloned().fold(f64::MAX, f64::min);
             let overall_avg_fitness: f64 = average_fitnesses.iter().sum::<f64>() / num_runs as f64;
 
             println!("Overall Best Fitness: {}", overall_best_fitness);
             println!("Overall Average Fitness: {}", overall_avg_fitness);
-            
-            let test_data = read_dataset_from_file("vlad_test.txt").unwrap();
 
             // print best expression from each run
-            for (i, expr) in best_expressions.iter().enumerate() {
+            for (i, (expr, test_fitness)) in best_expressions.iter().enumerate() {
                 println!("Run {}: Best Expression: {}", i+1, expr);
-                // println!("Example prediction:");
-                // for (x,y) in test_data.iter().take(5) {
-                //     match parse_expression(&expr) {
-                //         Ok(v) => {
-                //             let predicted_value = evaluate(&v, x);
-                //             println!("Predicted: {} Actual: {}", predicted_value, y)
-                //         }
-                //         Err(_e) => {
-                //             println!("Invalid expression");
-                //         } 
-                //     };
-                    
-                // }
-                
-                // test_fitness[i] = evaluate_fitness(&expr, &test_data);
-                // println!("Run {}: test fitness: {}", i+1, test_fitness[i]);
+                if let Some(test_fitness) = test_fitness {
+                    println!("Run {}: test fitness: {}", i+1, test_fitness);
+                }
             }
         },
         Err(e) => {
@@ -0,0 +1,120 @@
+use rand::Rng;
+
+use crate::evolutionary_ops::{tournament_index, TieBreak};
+use crate::fitness::{Direction, Fitness};
+use crate::individual::Individual;
+
+// How the size of an individual is measured for bloat control.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Size {
+    // Codons read by the mapper. Needs the individual to be mapped.
+    #[default]
+    UsedCodons,
+    GenomeLength,
+    // Depth of the derivation tree. Needs the individual to be mapped.
+    Depth,
+}
+
+impl Size {
+    pub fn of<F>(self, individual: &Individual<F>) -> usize {
+        match self {
+            Size::UsedCodons => individual.used_codons,
+            Size::GenomeLength => individual.genome.len(),
+            Size::Depth => individual.depth,
+        }
+    }
+}
+
+// Size pressure applied in tournament selection.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Parsimony {
+    #[default]
+    None,
+    // Lexicographic parsimony pressure: of equally fit contenders the smaller wins.
+    Lexicographic,
+    // Double tournament: the winners of two fitness tournaments meet in a size tournament,
+    // which the smaller wins with probability `size_pressure / 2`. `size_pressure` goes from
+    // 1 (no pressure) to 2 (the smaller always wins); around 1.4 works well.
+    DoubleTournament { size_pressure: f64 },
+}
+
+// Bloat control for the engine. Everything is off by default.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BloatControl {
+    // What "larger" means for the parsimony pressure and the Tarpeian method.
+    pub size: Size,
+    // Only applies to tournament selection.
+    pub parsimony: Parsimony,
+    // Tarpeian method: the probability with which a new individual larger than the average
+    // of its parents' generation is given the fitness of an invalid individual instead of
+    // being evaluated.
+    pub tarpeian: Option<f64>,
+    // Offspring with a longer genome are replaced by a copy of their parent.
+    pub max_genome_length: Option<usize>,
+    // Offspring with a deeper derivation are replaced by a copy of their parent.
+    pub max_depth: Option<usize>,
+}
+
+impl BloatControl {
+    // Whether `individual` is within the hard limits. It has to be mapped if there is a depth
+    // limit.
+    pub fn within_limits<F>(&self, individual: &Individual<F>) -> bool {
+        self.max_genome_length.is_none_or(|limit| individual.genome.len() <= limit)
+            && self.max_depth.is_none_or(|limit| individual.depth <= limit)
+    }
+}
+
+// Double tournament selection, returning the index of the winner. Each fitness tournament
+// breaks ties with `tie_break`.
+pub fn double_tournament_index<F: Fitness>(
+    population: &[Individual<F>],
+    tournament_size: usize,
+    size_pressure: f64,
+    size: Size,
+    direction: Direction,
+    tie_break: TieBreak,
+    rng: &mut impl Rng,
+) -> usize {
+    let a = tournament_index(population, tournament_size, direction, tie_break, rng);
+    let b = tournament_index(population, tournament_size, direction, tie_break, rng);
+    let (smaller, larger) = if size.of(&population[b]) < size.of(&population[a]) { (b, a) } else { (a, b) };
+    let probability = if size_pressure.is_nan() { 0.5 } else { size_pressure.clamp(1.0, 2.0) / 2.0 };
+    if rng.gen_bool(probability) { smaller } else { larger }
+}
+
+pub fn average_size<F>(population: &[Individual<F>], size: Size) -> f64 {
+    population.iter().map(|individual| size.of(individual) as f64).sum::<f64>() / population.len().max(1) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random;
+
+    #[test]
+    fn test_double_tournament() {
+        let mut rng = random::seeded(4);
+        let population: Vec<Individual> = [(1.0, 10), (1.0, 2), (5.0, 1)]
+            .into_iter()
+            .map(|(fitness, length)| Individual { fitness: Some(fitness), ..Individual::new(vec![0; length]) })
+            .collect();
+        let mut counts = [0; 3];
+        for _ in 0..1000 {
+            counts[double_tournament_index(&population, 30, 2.0, Size::GenomeLength, Direction::Minimize, TieBreak::First, &mut rng)] += 1;
+        }
+        // The fitness tournaments are won by the first two, and the smaller always wins on size
+        // unless both winners are the same.
+        assert_eq!(counts[2], 0);
+        assert!(counts[1] > 600);
+        assert_eq!(average_size(&population, Size::GenomeLength), 13.0 / 3.0);
+    }
+
+    #[test]
+    fn test_limits() {
+        let individual: Individual = Individual { depth: 5, ..Individual::new(vec![0; 10]) };
+        assert!(BloatControl::default().within_limits(&individual));
+        assert!(BloatControl { max_genome_length: Some(10), max_depth: Some(5), ..BloatControl::default() }.within_limits(&individual));
+        assert!(!BloatControl { max_genome_length: Some(9), ..BloatControl::default() }.within_limits(&individual));
+        assert!(!BloatControl { max_depth: Some(4), ..BloatControl::default() }.within_limits(&individual));
+    }
+}
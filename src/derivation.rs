@@ -0,0 +1,108 @@
+use std::fmt::Write;
+
+use crate::grammar::{Grammar, Symbol};
+
+// The production a non-terminal was expanded with, and the genome position of the codon
+// that chose it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Choice {
+    pub production: usize,
+    pub codon: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DerivationNode {
+    pub symbol: Symbol,
+    // `None` for terminals and for non-terminals left unexpanded by an invalid mapping.
+    pub choice: Option<Choice>,
+    // Indices into `DerivationTree::nodes`, left to right.
+    pub children: Vec<usize>,
+    // The root is at depth 1.
+    pub depth: usize,
+}
+
+// Derivation tree stored as an arena; `nodes[0]` is the start symbol.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DerivationTree {
+    pub nodes: Vec<DerivationNode>,
+}
+
+impl DerivationTree {
+    pub fn new(root: Symbol) -> Self {
+        DerivationTree {
+            nodes: vec![DerivationNode { symbol: root, choice: None, children: Vec::new(), depth: 1 }],
+        }
+    }
+
+    pub fn root(&self) -> &DerivationNode {
+        &self.nodes[0]
+    }
+
+    // Records the expansion of `parent` and returns the ids of the new children.
+    pub fn expand(&mut self, parent: usize, choice: Choice, symbols: &[Symbol]) -> std::ops::Range<usize> {
+        let depth = self.nodes[parent].depth + 1;
+        let first = self.nodes.len();
+        for &symbol in symbols {
+            self.nodes.push(DerivationNode { symbol, choice: None, children: Vec::new(), depth });
+        }
+        let children = first..self.nodes.len();
+        self.nodes[parent].choice = Some(choice);
+        self.nodes[parent].children = children.clone().collect();
+        children
+    }
+
+    // Node ids in depth-first, left-to-right order.
+    pub fn preorder(&self) -> Vec<usize> {
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut stack = vec![0];
+        while let Some(id) = stack.pop() {
+            order.push(id);
+            stack.extend(self.nodes[id].children.iter().rev());
+        }
+        order
+    }
+
+    // Number of expanded non-terminals on the longest root-to-leaf path.
+    pub fn depth(&self) -> usize {
+        self.nodes.iter().filter(|node| node.choice.is_some()).map(|node| node.depth).max().unwrap_or(0)
+    }
+
+    // The leaves from left to right, separated by single spaces, as the mapper writes them.
+    pub fn phenotype(&self, grammar: &Grammar) -> String {
+        let mut leaves = Vec::new();
+        for id in self.preorder() {
+            let node = &self.nodes[id];
+            match node.symbol {
+                Symbol::Terminal(terminal) => leaves.push(grammar.terminal(terminal).to_string()),
+                Symbol::NonTerminal(rule) if node.choice.is_none() => {
+                    leaves.push(format!("<{}>", grammar.rule(rule).name))
+                }
+                Symbol::NonTerminal(_) => {}
+            }
+        }
+        leaves.join(" ")
+    }
+
+    // Graphviz source for the tree. Non-terminals are labelled with their name, the chosen
+    // production and the codon position, e.g. `<e> 1 @3`.
+    pub fn to_dot(&self, grammar: &Grammar) -> String {
+        let mut dot = String::from("digraph derivation {\n");
+        for (id, node) in self.nodes.iter().enumerate() {
+            let label = match (node.symbol, node.choice) {
+                (Symbol::Terminal(terminal), _) => grammar.terminal(terminal).to_string(),
+                (Symbol::NonTerminal(rule), Some(choice)) => {
+                    format!("<{}> {} @{}", grammar.rule(rule).name, choice.production, choice.codon)
+                }
+                (Symbol::NonTerminal(rule), None) => format!("<{}>", grammar.rule(rule).name),
+            };
+            let shape = if matches!(node.symbol, Symbol::Terminal(_)) { "plaintext" } else { "ellipse" };
+            let label = label.replace('\\', "\\\\").replace('"', "\\\"");
+            writeln!(dot, "  n{} [label=\"{}\", shape={}];", id, label, shape).unwrap();
+            for child in &node.children {
+                writeln!(dot, "  n{} -> n{};", id, child).unwrap();
+            }
+        }
+        dot.push('}');
+        dot
+    }
+}
@@ -0,0 +1,1101 @@
+use std::fmt;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::bloat::{average_size, double_tournament_index, BloatControl, Parsimony};
+use crate::evolutionary_ops::{mutate, tournament_index, Crossover, Lexicase, ParentSelection, Selection, TieBreak};
+use crate::fitness::{Direction, Fitness};
+use crate::grammar::{Grammar, GrammarError};
+use crate::individual::{best, IdGenerator, Individual, Population};
+use crate::initialization::{DepthDistribution, Encoding, SensibleInitializer};
+use crate::mapper::Mapper;
+use crate::pge::ProductionWeights;
+use crate::population_mgmt::{
+    crowded_tournament_index, elite, migrate, nsga2_survivors, pareto_ranks, random_initialization, replace, IslandModel,
+    ParetoRank, Replacement, ReplacementPolicy,
+};
+use crate::random::{self, EvolutionRng};
+use crate::sge::SgeGrammar;
+
+// A problem to evolve solutions for: the grammar solutions are written in and how good a
+// phenotype is. Problems are shared between threads when evaluating in parallel.
+pub trait Problem: Sync {
+    type Fitness: Fitness;
+
+    fn grammar(&self) -> &Grammar;
+
+    fn direction(&self) -> Direction;
+
+    // Fitness of a valid phenotype on the training data.
+    fn evaluate(&self, phenotype: &str) -> Self::Fitness;
+
+    // Fitness of a valid individual. By default that of its phenotype; problems can override
+    // it to also use what mapping found out, e.g. used codons or depth as extra objectives.
+    fn evaluate_individual(&self, individual: &Individual<Self::Fitness>) -> Self::Fitness {
+        self.evaluate(individual.phenotype.as_ref().unwrap())
+    }
+
+    // Fitness given to individuals whose mapping is invalid.
+    fn invalid_fitness(&self) -> Self::Fitness;
+
+    // Error of a valid phenotype on each training case, lower being better, for lexicase
+    // selection. Only called when the engine selects parents by lexicase.
+    fn evaluate_cases(&self, _phenotype: &str) -> Option<Vec<f64>> {
+        None
+    }
+
+    // Fitness of a phenotype on held-out data, if the problem has any.
+    fn evaluate_test(&self, _phenotype: &str) -> Option<Self::Fitness> {
+        None
+    }
+
+    // Whether `fitness` is good enough to stop the run early.
+    fn is_solved(&self, _fitness: &Self::Fitness) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InitialPopulation {
+    // Uniformly random genomes of `length` codons.
+    Random { length: usize },
+    Sensible(SensibleInitializer),
+}
+
+// How genomes encode phenotypes.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Representation {
+    // Classic GE: a variable-length genome mapped by `Config::mapper`.
+    #[default]
+    Ge,
+    // Structured GE (`sge::SgeGrammar`), with recursive rules unrolled to `max_depth` levels.
+    // Genomes have a fixed length and are created and mutated by the SGE operators, so
+    // `Config::initial_population` is ignored and crossover must be one-point. Only
+    // `Mapper::build_tree` of `Config::mapper` applies.
+    Sge { max_depth: usize },
+    // Probabilistic GE: codons choose productions by roulette wheel over production weights,
+    // which start as the grammar's weights. After every generation the weights move towards
+    // the productions used by the generation's best individual and the best of the run so far
+    // (`pge::ProductionWeights::update`), by `learning_factor` between 0 (fixed weights) and 1.
+    // Derivation trees are always built. Individuals keep the mapping they were evaluated with.
+    Pge { learning_factor: f64 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub population_size: usize,
+    pub generations: usize,
+    pub initial_population: InitialPopulation,
+    // Read by `Engine::new`.
+    pub representation: Representation,
+    pub mapper: Mapper,
+    pub selection: ParentSelection,
+    pub tournament_size: usize,
+    pub tie_break: TieBreak,
+    pub bloat_control: BloatControl,
+    pub crossover: Crossover,
+    // Probability that a pair of parents is recombined rather than copied.
+    pub crossover_probability: f64,
+    // Probability that an offspring has one codon changed. With SGE, the probability that each
+    // gene used by the mapping is changed.
+    pub mutation_probability: f64,
+    pub replacement: Replacement,
+    // Best individuals copied unchanged into the next generation, or with steady-state
+    // replacement, never replaced. Also protects each island's best from immigrants.
+    pub elite_size: usize,
+    // Evolve `population_size` individuals on each of several islands instead of a single
+    // population.
+    pub islands: Option<IslandModel>,
+    // Print the best individual of every generation.
+    pub verbose: bool,
+    // Threads used to map and evaluate individuals with the `parallel` feature, or `None` for
+    // one per core. Ignored without the feature. Read by `Engine::new`.
+    pub threads: Option<usize>,
+    // Seed of the run's random number generator, or `None` for a fresh one. The seed used is
+    // reported in `RunResult::seed`, so any run can be repeated exactly.
+    pub seed: Option<u64>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            population_size: 100,
+            generations: 50,
+            initial_population: InitialPopulation::Sensible(SensibleInitializer::default()),
+            representation: Representation::Ge,
+            mapper: Mapper::default(),
+            selection: ParentSelection::Tournament,
+            tournament_size: 3,
+            tie_break: TieBreak::Random,
+            bloat_control: BloatControl::default(),
+            crossover: Crossover::OnePoint,
+            crossover_probability: 0.9,
+            mutation_probability: 0.1,
+            replacement: Replacement::Generational,
+            elite_size: 1,
+            islands: None,
+            verbose: false,
+            threads: None,
+            seed: None,
+        }
+    }
+}
+
+impl Config {
+    // Checks the settings that would otherwise only fail in the middle of a run.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.population_size == 0 {
+            return Err(ConfigError::EmptyPopulation);
+        }
+        if self.islands.as_ref().is_some_and(|model| model.islands == 0) {
+            return Err(ConfigError::NoIslands);
+        }
+        if !(0.0..=1.0).contains(&self.crossover_probability) {
+            return Err(ConfigError::CrossoverProbability(self.crossover_probability));
+        }
+        if !(0.0..=1.0).contains(&self.mutation_probability) {
+            return Err(ConfigError::MutationProbability(self.mutation_probability));
+        }
+        if self.tournament_size == 0 {
+            return Err(ConfigError::TournamentSize(self.tournament_size));
+        }
+        if let ParentSelection::DownsampledLexicase { rate } = self.selection {
+            if !(rate > 0.0 && rate <= 1.0) {
+                return Err(ConfigError::DownsampleRate(rate));
+            }
+        }
+        if let Some(probability) = self.bloat_control.tarpeian {
+            if !(0.0..=1.0).contains(&probability) {
+                return Err(ConfigError::TarpeianProbability(probability));
+            }
+        }
+        if let Representation::Pge { learning_factor } = self.representation {
+            if !(0.0..=1.0).contains(&learning_factor) {
+                return Err(ConfigError::LearningFactor(learning_factor));
+            }
+        }
+        if matches!(self.representation, Representation::Sge { .. }) && self.crossover != Crossover::OnePoint {
+            return Err(ConfigError::SgeCrossover(self.crossover));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    // `population_size` is 0.
+    EmptyPopulation,
+    // The island model has no islands.
+    NoIslands,
+    CrossoverProbability(f64),
+    MutationProbability(f64),
+    // Tournaments need at least one contender.
+    TournamentSize(usize),
+    // The downsampled lexicase rate is not in (0, 1].
+    DownsampleRate(f64),
+    // `BloatControl::tarpeian` is not a probability.
+    TarpeianProbability(f64),
+    // The PGE learning factor is not between 0 and 1.
+    LearningFactor(f64),
+    // SGE genomes have a fixed layout, which only one-point crossover keeps.
+    SgeCrossover(Crossover),
+    // The problem's grammar cannot be unrolled for SGE.
+    Sge(GrammarError),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::EmptyPopulation => write!(f, "the population size is 0"),
+            ConfigError::NoIslands => write!(f, "the island model has no islands"),
+            ConfigError::CrossoverProbability(probability) => {
+                write!(f, "crossover probability {} is not between 0 and 1", probability)
+            }
+            ConfigError::MutationProbability(probability) => {
+                write!(f, "mutation probability {} is not between 0 and 1", probability)
+            }
+            ConfigError::TournamentSize(size) => write!(f, "tournament size {} is not at least 1", size),
+            ConfigError::DownsampleRate(rate) => write!(f, "downsampled lexicase rate {} is not in (0, 1]", rate),
+            ConfigError::TarpeianProbability(probability) => {
+                write!(f, "Tarpeian probability {} is not between 0 and 1", probability)
+            }
+            ConfigError::LearningFactor(factor) => write!(f, "PGE learning factor {} is not between 0 and 1", factor),
+            ConfigError::SgeCrossover(crossover) => write!(f, "SGE genomes cannot use {:?} crossover", crossover),
+            ConfigError::Sge(e) => write!(f, "SGE: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Sge(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenerationStats<F> {
+    pub generation: usize,
+    pub best_fitness: F,
+    pub best_phenotype: String,
+    pub invalid: usize,
+    // The same statistics for each island of an island model, or empty.
+    pub islands: Vec<GenerationStats<F>>,
+}
+
+impl<F: Fitness> GenerationStats<F> {
+    fn new<'a>(generation: usize, population: impl IntoIterator<Item = &'a Individual<F>>, direction: Direction) -> Self
+    where
+        F: 'a,
+    {
+        let mut best: Option<&Individual<F>> = None;
+        let mut invalid = 0;
+        for individual in population {
+            if best.is_none_or(|best| individual.compare(best, direction).is_lt()) {
+                best = Some(individual);
+            }
+            invalid += usize::from(!individual.valid);
+        }
+        let best = best.expect("the population is not empty");
+        GenerationStats {
+            generation,
+            best_fitness: best.fitness.clone().unwrap(),
+            best_phenotype: best.phenotype.clone().unwrap(),
+            invalid,
+            islands: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunResult<F> {
+    // Best individual of the whole run.
+    pub best: Individual<F>,
+    pub test_fitness: Option<F>,
+    // One entry per evaluated generation, starting with the initial population.
+    pub history: Vec<GenerationStats<F>>,
+    // With an island model, the islands one after the other.
+    pub final_population: Population<F>,
+    // The production weights at the end of a PGE run.
+    pub production_weights: Option<ProductionWeights>,
+    // Derivation depths of the valid individuals of the initial population, over all islands.
+    pub initial_depths: DepthDistribution,
+    pub seed: u64,
+}
+
+// The GE loop: initialise, evaluate, select, vary and replace until the
+// generation limit is reached or the problem is solved.
+pub struct Engine<P: Problem> {
+    pub problem: P,
+    pub config: Config,
+    // The unrolled grammar when the representation is SGE.
+    sge: Option<SgeGrammar>,
+    #[cfg(feature = "parallel")]
+    pool: rayon::ThreadPool,
+}
+
+impl<P: Problem> Engine<P> {
+    // Panics if the configuration is invalid; see `Engine::try_new`.
+    pub fn new(problem: P, config: Config) -> Self {
+        Engine::try_new(problem, config).unwrap_or_else(|error| panic!("invalid engine configuration: {}", error))
+    }
+
+    pub fn try_new(problem: P, config: Config) -> Result<Self, ConfigError> {
+        config.validate()?;
+        let sge = match config.representation {
+            Representation::Sge { max_depth } => Some(SgeGrammar::new(problem.grammar(), max_depth).map_err(ConfigError::Sge)?),
+            _ => None,
+        };
+        Ok(Engine {
+            #[cfg(feature = "parallel")]
+            pool: rayon::ThreadPoolBuilder::new()
+                .num_threads(config.threads.unwrap_or(0))
+                .build()
+                .expect("failed to start the evaluation threads"),
+            problem,
+            config,
+            sge,
+        })
+    }
+
+    pub fn run(&self) -> RunResult<P::Fitness> {
+        self.run_stream(0)
+    }
+
+    // A run using stream `stream` of the configured seed. Repeated runs of an experiment can
+    // share one seed and differ only in their stream.
+    pub fn run_stream(&self, stream: u64) -> RunResult<P::Fitness> {
+        let seed = self.config.seed.unwrap_or_else(random::random_seed);
+        if self.config.verbose {
+            println!("Seed: {} (stream {})", seed, stream);
+        }
+        let mut rng = random::stream(seed, stream);
+        // The production weights of a PGE run, which only change between generations.
+        let mut weights = match self.config.representation {
+            Representation::Pge { .. } => Some(ProductionWeights::new(self.problem.grammar())),
+            _ => None,
+        };
+        let direction = self.problem.direction();
+        let model = self.config.islands.as_ref();
+
+        // Each island evolves with its own random number and id generators, so the result does
+        // not depend on the order in which islands are processed. Migration uses the run's
+        // random number generator.
+        let count = model.map_or(1, |model| model.islands);
+        let mut rngs: Vec<EvolutionRng> = (0..count).map(|_| random::seeded(rng.gen())).collect();
+        let mut ids: Vec<IdGenerator> = (0..count).map(|island| IdGenerator::new(island, count)).collect();
+        let mut islands: Vec<Population<P::Fitness>> =
+            ids.iter_mut().zip(&mut rngs).map(|(ids, rng)| self.initial_population(weights.as_ref(), ids, rng)).collect();
+        let mut history = Vec::new();
+        let mut best_so_far: Option<Individual<P::Fitness>> = None;
+        let mut initial_depths = DepthDistribution::default();
+
+        for generation in 0..=self.config.generations {
+            let current = weights.as_ref();
+            self.for_each_island(&mut islands, &mut ids, &mut rngs, |population, ids, rng| {
+                if generation > 0 {
+                    self.step(population, current, ids, rng);
+                }
+                self.evaluate(population, current);
+            });
+            if let Some(model) = model {
+                if generation > 0 && generation % model.migration_interval.max(1) == 0 {
+                    migrate(&mut islands, &mut ids, model, self.config.elite_size, direction, &mut rng);
+                }
+            }
+
+            if generation == 0 {
+                let valid = islands.iter().flatten().filter(|individual| individual.valid);
+                initial_depths = DepthDistribution::from_depths(valid.map(|individual| individual.depth));
+                if self.config.verbose {
+                    println!("Initial depths:\n{}", initial_depths);
+                }
+            }
+            let mut stats = GenerationStats::new(generation, islands.iter().flatten(), direction);
+            if model.is_some() {
+                stats.islands = islands.iter().map(|population| GenerationStats::new(generation, population, direction)).collect();
+            }
+            let generation_best = islands
+                .iter()
+                .map(|population| best(population, direction).expect("the population is not empty"))
+                .min_by(|a, b| a.compare(b, direction))
+                .unwrap();
+            if best_so_far.as_ref().is_none_or(|best| generation_best.compare(best, direction).is_lt()) {
+                best_so_far = Some(generation_best.clone());
+            }
+            if let (Some(weights), Representation::Pge { learning_factor }) = (&mut weights, &self.config.representation) {
+                let trees = [generation_best, best_so_far.as_ref().unwrap()]
+                    .into_iter()
+                    .filter(|individual| individual.valid)
+                    .filter_map(|individual| individual.tree.as_ref());
+                weights.update(trees, *learning_factor);
+            }
+            if self.config.verbose {
+                println!(
+                    "Generation {}: Best Genome (Fitness = {:?}): {:?}",
+                    generation, stats.best_fitness, stats.best_phenotype
+                );
+                for (i, island) in stats.islands.iter().enumerate() {
+                    println!("  Island {}: Best Fitness = {:?}, invalid = {}", i, island.best_fitness, island.invalid);
+                }
+            }
+            let solved = self.problem.is_solved(&stats.best_fitness);
+            history.push(stats);
+            if solved {
+                break;
+            }
+        }
+
+        let best = best_so_far.unwrap();
+        let test_fitness = if best.valid { self.problem.evaluate_test(best.phenotype.as_ref().unwrap()) } else { None };
+        let final_population = islands.into_iter().flatten().collect();
+        RunResult { best, test_fitness, history, final_population, production_weights: weights, initial_depths, seed }
+    }
+
+    // Runs `f` on every island, on the engine's threads with the `parallel` feature.
+    fn for_each_island<F>(&self, islands: &mut [Population<P::Fitness>], ids: &mut [IdGenerator], rngs: &mut [EvolutionRng], f: F)
+    where
+        F: Fn(&mut Population<P::Fitness>, &mut IdGenerator, &mut EvolutionRng) + Send + Sync,
+    {
+        #[cfg(feature = "parallel")]
+        self.pool.install(|| {
+            islands.par_iter_mut().zip(ids).zip(rngs).for_each(|((population, ids), rng)| f(population, ids, rng))
+        });
+        #[cfg(not(feature = "parallel"))]
+        islands.iter_mut().zip(ids).zip(rngs).for_each(|((population, ids), rng)| f(population, ids, rng));
+    }
+
+    // Breeds the next generation of `population` with the configured replacement.
+    fn step(
+        &self,
+        population: &mut Population<P::Fitness>,
+        weights: Option<&ProductionWeights>,
+        ids: &mut IdGenerator,
+        rng: &mut EvolutionRng,
+    ) {
+        match self.config.replacement {
+            Replacement::Generational => *population = self.next_generation(population, weights, ids, rng),
+            Replacement::SteadyState { offspring, policy } => {
+                self.steady_state_generation(population, offspring, policy, weights, ids, rng)
+            }
+            Replacement::Nsga2 => *population = self.nsga2_generation(population, weights, ids, rng),
+        }
+    }
+
+    // The initial population. Sensible initialisation encodes its derivations for the mapping
+    // strategy and, in a PGE run, for the production `weights` the run starts with.
+    pub fn initial_population(
+        &self,
+        weights: Option<&ProductionWeights>,
+        ids: &mut IdGenerator,
+        rng: &mut EvolutionRng,
+    ) -> Population<P::Fitness> {
+        let size = self.config.population_size;
+        let genomes = match (&self.sge, &self.config.initial_population) {
+            (Some(sge), _) => sge.initialize_population(size, rng),
+            (None, InitialPopulation::Random { length }) => return random_initialization(size, *length, 256, ids, rng),
+            (None, InitialPopulation::Sensible(initializer)) => {
+                let encoding = Encoding { strategy: self.config.mapper.strategy, weights };
+                initializer.initialize_population(self.problem.grammar(), size, encoding, rng)
+            }
+        };
+        genomes.into_iter().map(|genome| Individual::offspring(genome, &[], ids)).collect()
+    }
+
+    // Maps and evaluates the individuals that have not been evaluated yet, with the current
+    // production weights of a PGE run. With the `parallel` feature individuals are spread over
+    // the engine's threads; each result depends only on its individual, so the outcome is the
+    // same for any number of threads.
+    pub fn evaluate(&self, population: &mut Population<P::Fitness>, weights: Option<&ProductionWeights>) {
+        let fitness = |individual: &Individual<P::Fitness>| {
+            if individual.valid {
+                self.problem.evaluate_individual(individual)
+            } else {
+                self.problem.invalid_fitness()
+            }
+        };
+        let cases = self.config.selection.uses_cases();
+        let evaluate = |individual: &mut Individual<P::Fitness>| {
+            self.map(individual, weights);
+            individual.evaluate(&fitness);
+            if cases && individual.valid && individual.case_errors.is_none() {
+                individual.case_errors = self.problem.evaluate_cases(individual.phenotype.as_ref().unwrap());
+            }
+        };
+
+        #[cfg(feature = "parallel")]
+        self.pool.install(|| population.par_iter_mut().for_each(evaluate));
+        #[cfg(not(feature = "parallel"))]
+        population.iter_mut().for_each(evaluate);
+    }
+
+    // Generational replacement: the elite survive and the rest of the population is bred
+    // from parents chosen by tournament.
+    pub fn next_generation(
+        &self,
+        population: &Population<P::Fitness>,
+        weights: Option<&ProductionWeights>,
+        ids: &mut IdGenerator,
+        rng: &mut EvolutionRng,
+    ) -> Population<P::Fitness> {
+        let size = self.config.population_size;
+        let mut selector = self.selector(population, rng);
+        let mut next: Population<P::Fitness> = elite(population, self.config.elite_size, self.problem.direction())
+            .into_iter()
+            .map(|index| population[index].survivor())
+            .collect();
+
+        while next.len() < size {
+            let (children, _) = self.breed(population, &mut selector, weights, ids, rng);
+            next.extend(children.into_iter().take(size - next.len()));
+        }
+        self.tarpeian(&mut next, population, weights, rng);
+        next
+    }
+
+    // Steady-state replacement: `offspring` (1 or 2) children at a time are bred, evaluated and
+    // put in place of individuals chosen by `policy`, until `population_size` children have
+    // been bred.
+    pub fn steady_state_generation(
+        &self,
+        population: &mut Population<P::Fitness>,
+        offspring: usize,
+        policy: ReplacementPolicy,
+        weights: Option<&ProductionWeights>,
+        ids: &mut IdGenerator,
+        rng: &mut EvolutionRng,
+    ) {
+        // Lexicase cases and epsilons are worked out once per generation.
+        let mut selector = self.selector(population, rng);
+        let mut bred = 0;
+        while bred < self.config.population_size {
+            let (children, parents) = self.breed(population, &mut selector, weights, ids, rng);
+            let count = offspring.clamp(1, 2).min(self.config.population_size - bred);
+            let mut children: Population<P::Fitness> = children.into_iter().take(count).collect();
+            bred += count;
+            self.tarpeian(&mut children, population, weights, rng);
+            self.evaluate(&mut children, weights);
+            replace(population, children, &parents, policy, self.config.elite_size, self.problem.direction(), rng);
+            // The parents a scheme picked ahead are indices into the population as it was, so
+            // the scheme is drawn again on the population with the new children.
+            if let Selector::Scheme(_, parents) = &mut selector {
+                parents.clear();
+            }
+        }
+    }
+
+    // NSGA-II: `population_size` offspring are bred from parents chosen on Pareto rank, and the
+    // best of parents and offspring survive.
+    pub fn nsga2_generation(
+        &self,
+        population: &Population<P::Fitness>,
+        weights: Option<&ProductionWeights>,
+        ids: &mut IdGenerator,
+        rng: &mut EvolutionRng,
+    ) -> Population<P::Fitness> {
+        let size = self.config.population_size;
+        let mut selector = Selector::Pareto(pareto_ranks(population, self.problem.direction()));
+        let mut offspring = Population::with_capacity(size);
+        while offspring.len() < size {
+            let (children, _) = self.breed(population, &mut selector, weights, ids, rng);
+            offspring.extend(children.into_iter().take(size - offspring.len()));
+        }
+        self.tarpeian(&mut offspring, population, weights, rng);
+        self.evaluate(&mut offspring, weights);
+
+        let mut combined: Population<P::Fitness> = population.iter().map(Individual::survivor).collect();
+        combined.extend(offspring);
+        nsga2_survivors(combined, size, self.problem.direction())
+    }
+
+    fn selector(&self, population: &[Individual<P::Fitness>], rng: &mut EvolutionRng) -> Selector<P::Fitness> {
+        match self.config.selection {
+            ParentSelection::Tournament => Selector::Tournament,
+            ParentSelection::Lexicase => Selector::Lexicase(Lexicase::new(population)),
+            ParentSelection::EpsilonLexicase => Selector::Lexicase(Lexicase::epsilon(population)),
+            ParentSelection::DownsampledLexicase { rate } => Selector::Lexicase(Lexicase::downsampled(population, rate, rng)),
+            selection => Selector::Scheme(selection.scheme().unwrap(), Vec::new()),
+        }
+    }
+
+    fn select(&self, population: &[Individual<P::Fitness>], selector: &mut Selector<P::Fitness>, rng: &mut EvolutionRng) -> usize {
+        let config = &self.config;
+        match selector {
+            // A whole generation's parents are drawn at once and handed out in random order;
+            // steady-state replacement draws them again after every breeding step.
+            Selector::Scheme(scheme, parents) => {
+                if parents.is_empty() {
+                    *parents = scheme.select(population, population.len(), self.problem.direction(), rng);
+                    parents.shuffle(rng);
+                }
+                parents.pop().unwrap()
+            }
+            Selector::Tournament => {
+                let direction = self.problem.direction();
+                let size = config.bloat_control.size;
+                match config.bloat_control.parsimony {
+                    Parsimony::None => tournament_index(population, config.tournament_size, direction, config.tie_break, rng),
+                    Parsimony::Lexicographic => {
+                        tournament_index(population, config.tournament_size, direction, TieBreak::Smaller(size), rng)
+                    }
+                    Parsimony::DoubleTournament { size_pressure } => double_tournament_index(
+                        population,
+                        config.tournament_size,
+                        size_pressure,
+                        size,
+                        direction,
+                        config.tie_break,
+                        rng,
+                    ),
+                }
+            }
+            Selector::Lexicase(lexicase) => lexicase.select_index(population, rng),
+            Selector::Pareto(ranks) => crowded_tournament_index(ranks, rng),
+        }
+    }
+
+    // Tarpeian method: new individuals of `offspring` larger than the average of `parents` are
+    // given the invalid fitness, each with the configured probability, so they are never
+    // evaluated. They also get an empty list of case errors, which lexicase selection counts
+    // as infinitely bad on every case.
+    fn tarpeian(
+        &self,
+        offspring: &mut [Individual<P::Fitness>],
+        parents: &[Individual<P::Fitness>],
+        weights: Option<&ProductionWeights>,
+        rng: &mut EvolutionRng,
+    ) {
+        let Some(probability) = self.config.bloat_control.tarpeian else {
+            return;
+        };
+        let size = self.config.bloat_control.size;
+        let average = average_size(parents, size);
+        for individual in offspring.iter_mut().filter(|individual| !individual.is_evaluated()) {
+            self.map(individual, weights);
+            if size.of(individual) as f64 > average && rng.gen_bool(probability) {
+                individual.fitness = Some(self.problem.invalid_fitness());
+                individual.case_errors = Some(Vec::new());
+            }
+        }
+    }
+
+    // Maps `individual` with the configured representation, unless it has been mapped already.
+    // `weights` are the production weights of a PGE run, and `None` otherwise.
+    fn map(&self, individual: &mut Individual<P::Fitness>, weights: Option<&ProductionWeights>) {
+        if let Some(sge) = &self.sge {
+            individual.map_with(|genome| sge.map(genome, self.config.mapper.build_tree));
+        } else if let Some(weights) = weights {
+            let mapper = Mapper { build_tree: true, ..self.config.mapper.clone() };
+            individual.map_with(|genome| mapper.map_probabilistic(self.problem.grammar(), genome, weights));
+        } else {
+            individual.map(self.problem.grammar(), &self.config.mapper);
+        }
+    }
+
+    // Two children of parents chosen by `selector`, and the parents' indices. Each child is a
+    // crossover offspring or a copy of its parent, possibly mutated. Children over the hard
+    // size limits are replaced by copies of their parents.
+    fn breed(
+        &self,
+        population: &[Individual<P::Fitness>],
+        selector: &mut Selector<P::Fitness>,
+        weights: Option<&ProductionWeights>,
+        ids: &mut IdGenerator,
+        rng: &mut EvolutionRng,
+    ) -> ([Individual<P::Fitness>; 2], [usize; 2]) {
+        let config = &self.config;
+        let indices = [self.select(population, selector, rng), self.select(population, selector, rng)];
+        let parents = [&population[indices[0]], &population[indices[1]]];
+
+        let crossed = rng.gen_bool(config.crossover_probability) && !parents[0].genome.is_empty() && !parents[1].genome.is_empty();
+        let mut children = if crossed {
+            let (child1, child2) = config.crossover.apply(parents[0], parents[1], rng);
+            [Individual::offspring(child1, &parents, ids), Individual::offspring(child2, &parents, ids)]
+        } else {
+            [parents[0].copy(ids), parents[1].copy(ids)]
+        };
+
+        for (k, child) in children.iter_mut().enumerate() {
+            let mut genome = child.genome.clone();
+            match &self.sge {
+                Some(sge) => sge.mutate(&mut genome, config.mutation_probability, rng),
+                None if !genome.is_empty() && rng.gen_bool(config.mutation_probability) => mutate(&mut genome, rng),
+                None => {}
+            }
+            if genome != child.genome {
+                *child = Individual::offspring(genome, if crossed { &parents } else { &parents[k..=k] }, ids);
+            }
+        }
+
+        let limits = &config.bloat_control;
+        for (child, parent) in children.iter_mut().zip(parents) {
+            if limits.max_depth.is_some() {
+                self.map(child, weights);
+            }
+            if !limits.within_limits(child) {
+                *child = parent.copy(ids);
+            }
+        }
+        (children, indices)
+    }
+}
+
+// Parent selection set up for one generation.
+enum Selector<F: Fitness> {
+    Tournament,
+    Lexicase(Lexicase),
+    Pareto(Vec<ParetoRank>),
+    // A scheme and the parents it picked that have not been used yet.
+    Scheme(Box<dyn Selection<F>>, Vec<usize>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fitness::MultiObjective;
+    use crate::genome::Genome;
+    use crate::grammar::parse_grammar_str;
+    use crate::population_mgmt::pareto_front;
+
+    // Find an expression over x with as many `x`s as the target.
+    struct CountX {
+        grammar: Grammar,
+        target: usize,
+    }
+
+    impl Problem for CountX {
+        type Fitness = usize;
+
+        fn grammar(&self) -> &Grammar {
+            &self.grammar
+        }
+
+        fn direction(&self) -> Direction {
+            Direction::Minimize
+        }
+
+        fn evaluate(&self, phenotype: &str) -> usize {
+            phenotype.split(' ').filter(|token| *token == "x").count().abs_diff(self.target)
+        }
+
+        fn invalid_fitness(&self) -> usize {
+            usize::MAX
+        }
+
+        // Case `k` is failed with at most `k` `x`s, and a last case with more than the target.
+        fn evaluate_cases(&self, phenotype: &str) -> Option<Vec<f64>> {
+            let count = phenotype.split(' ').filter(|token| *token == "x").count();
+            let mut errors: Vec<f64> = (0..self.target).map(|k| f64::from(count <= k)).collect();
+            errors.push(f64::from(count > self.target));
+            Some(errors)
+        }
+
+        fn evaluate_test(&self, phenotype: &str) -> Option<usize> {
+            Some(phenotype.len())
+        }
+
+        fn is_solved(&self, fitness: &usize) -> bool {
+            *fitness == 0
+        }
+    }
+
+    fn count_x(target: usize) -> CountX {
+        CountX { grammar: parse_grammar_str("<e> ::= <e> + <e> | x | 1").unwrap(), target }
+    }
+
+    #[test]
+    fn test_engine_solves_problem() {
+        let config = Config { population_size: 50, generations: 100, seed: Some(1), ..Config::default() };
+        let result = Engine::new(count_x(4), config).run();
+        assert_eq!(result.best.fitness, Some(0));
+        assert_eq!(result.test_fitness, Some(result.best.phenotype.as_ref().unwrap().len()));
+        assert_eq!(result.history.last().unwrap().best_fitness, 0);
+        assert!(result.history.len() <= 101);
+        assert_eq!(result.final_population.len(), 50);
+        // Sensible initialisation ramps the depth up to 10.
+        let depths = &result.initial_depths.counts;
+        assert_eq!(depths.values().sum::<usize>(), 50);
+        assert!(depths.len() > 1 && depths.keys().all(|&depth| depth <= 10));
+    }
+
+    #[test]
+    fn test_elitism_never_loses_the_best() {
+        let config = Config {
+            population_size: 20,
+            generations: 10,
+            initial_population: InitialPopulation::Random { length: 20 },
+            mutation_probability: 1.0,
+            seed: Some(2),
+            ..Config::default()
+        };
+        let result = Engine::new(count_x(1000), config).run();
+        let best: Vec<usize> = result.history.iter().map(|stats| stats.best_fitness).collect();
+        assert!(best.windows(2).all(|pair| pair[1] <= pair[0]));
+        assert_eq!(result.history.len(), 11);
+    }
+
+    #[test]
+    fn test_steady_state() {
+        for policy in [ReplacementPolicy::Worst, ReplacementPolicy::Random, ReplacementPolicy::InverseTournament(2), ReplacementPolicy::Parent] {
+            let config = Config {
+                population_size: 30,
+                generations: 100,
+                replacement: Replacement::SteadyState { offspring: 1, policy },
+                seed: Some(3),
+                ..Config::default()
+            };
+            let result = Engine::new(count_x(4), config).run();
+            assert_eq!(result.best.fitness, Some(0), "{:?}", policy);
+            assert_eq!(result.final_population.len(), 30);
+            let best: Vec<usize> = result.history.iter().map(|stats| stats.best_fitness).collect();
+            assert!(best.windows(2).all(|pair| pair[1] <= pair[0]));
+        }
+    }
+
+    #[test]
+    fn test_crossover_variants() {
+        for crossover in [Crossover::Effective, Crossover::VariableLength, Crossover::EffectiveVariableLength] {
+            let config = Config { population_size: 50, generations: 50, crossover, seed: Some(4), ..Config::default() };
+            let result = Engine::new(count_x(6), config).run();
+            assert_eq!(result.best.fitness, Some(0), "{:?}", crossover);
+        }
+    }
+
+    #[test]
+    fn test_sge() {
+        let config = Config {
+            population_size: 50,
+            generations: 50,
+            representation: Representation::Sge { max_depth: 4 },
+            mapper: Mapper { build_tree: true, ..Mapper::default() },
+            seed: Some(5),
+            ..Config::default()
+        };
+        let engine = Engine::new(count_x(5), config);
+        let length = engine.sge.as_ref().unwrap().genome_length();
+        let result = engine.run();
+        assert_eq!(result.best.fitness, Some(0));
+        assert!(result.best.tree.is_some());
+        assert!(result.final_population.iter().all(|individual| individual.genome.len() == length && individual.valid));
+    }
+
+    #[test]
+    fn test_pge() {
+        let grammar = count_x(4).grammar;
+        let initial = ProductionWeights::new(&grammar);
+        for learning_factor in [0.0, 0.05] {
+            let config = Config {
+                population_size: 50,
+                generations: 100,
+                representation: Representation::Pge { learning_factor },
+                seed: Some(6),
+                ..Config::default()
+            };
+            let result = Engine::new(count_x(4), config).run();
+            assert_eq!(result.best.fitness, Some(0));
+            assert!(result.best.tree.is_some());
+            let weights = result.production_weights.unwrap();
+            assert_eq!(weights == initial, learning_factor == 0.0);
+        }
+
+        // Each run learns its own weights, so runs sharing an engine do not disturb each other.
+        let config = Config {
+            population_size: 20,
+            generations: 10,
+            representation: Representation::Pge { learning_factor: 0.2 },
+            seed: Some(6),
+            ..Config::default()
+        };
+        let engine = Engine::new(count_x(30), config);
+        let (a, b) = std::thread::scope(|scope| {
+            let a = scope.spawn(|| engine.run());
+            let b = scope.spawn(|| engine.run_stream(1));
+            (a.join().unwrap(), b.join().unwrap())
+        });
+        assert_eq!(a, engine.run());
+        assert_eq!(b, engine.run_stream(1));
+    }
+
+    #[test]
+    fn test_same_seed_same_run() {
+        let engine = Engine::new(count_x(1000), Config { population_size: 20, generations: 5, seed: Some(7), ..Config::default() });
+        let genomes = |result: &RunResult<usize>| -> Vec<Genome> {
+            result.final_population.iter().map(|individual| individual.genome.clone()).collect()
+        };
+        let (a, b) = (engine.run(), engine.run());
+        assert_eq!(a.seed, 7);
+        assert_eq!(a.history, b.history);
+        assert_eq!(genomes(&a), genomes(&b));
+        // Ids come from the run, not from the process, so they repeat too.
+        assert_eq!(a.final_population, b.final_population);
+        assert_ne!(genomes(&a), genomes(&engine.run_stream(1)));
+    }
+
+    #[test]
+    fn test_lexicase_selection() {
+        for selection in [ParentSelection::Lexicase, ParentSelection::EpsilonLexicase, ParentSelection::DownsampledLexicase { rate: 0.5 }] {
+            let config = Config { population_size: 30, generations: 100, selection, seed: Some(6), ..Config::default() };
+            let result = Engine::new(count_x(4), config).run();
+            assert_eq!(result.best.fitness, Some(0), "{:?}", selection);
+            assert!(result.final_population.iter().all(|individual| individual.case_errors.is_some() == individual.valid));
+        }
+        let result = Engine::new(count_x(4), Config { generations: 1, seed: Some(6), ..Config::default() }).run();
+        assert!(result.final_population.iter().all(|individual| individual.case_errors.is_none()));
+    }
+
+    #[test]
+    fn test_selection_schemes() {
+        let schemes = [
+            ParentSelection::RouletteWheel,
+            ParentSelection::StochasticUniversalSampling,
+            ParentSelection::LinearRank { pressure: 1.8 },
+            ParentSelection::ExponentialRank { base: 0.9 },
+            ParentSelection::Truncation { fraction: 0.3 },
+        ];
+        for selection in schemes {
+            let config = Config { population_size: 30, generations: 100, selection, seed: Some(9), ..Config::default() };
+            let result = Engine::new(count_x(4), config).run();
+            assert_eq!(result.best.fitness, Some(0), "{:?}", selection);
+            assert!(result.final_population.iter().all(|individual| individual.case_errors.is_none()));
+
+            let replacement = Replacement::SteadyState { offspring: 2, policy: ReplacementPolicy::Worst };
+            let config = Config { population_size: 30, generations: 100, selection, replacement, seed: Some(9), ..Config::default() };
+            let result = Engine::new(count_x(4), config).run();
+            assert_eq!(result.best.fitness, Some(0), "steady-state {:?}", selection);
+        }
+    }
+
+    #[test]
+    fn test_bloat_control() {
+        let bloat_control = BloatControl { max_genome_length: Some(100), max_depth: Some(6), ..BloatControl::default() };
+        let config = Config { population_size: 30, generations: 10, bloat_control, seed: Some(10), ..Config::default() };
+        let result = Engine::new(count_x(12), config).run();
+        let offspring: Vec<&Individual<usize>> = result.final_population.iter().filter(|individual| individual.age == 0 && !individual.parents.is_empty()).collect();
+        assert!(!offspring.is_empty());
+        assert!(offspring.iter().all(|individual| individual.genome.len() <= 100 && individual.depth <= 6));
+
+        for parsimony in [Parsimony::Lexicographic, Parsimony::DoubleTournament { size_pressure: 1.4 }] {
+            let bloat_control = BloatControl { parsimony, tarpeian: Some(0.3), ..BloatControl::default() };
+            let config = Config { population_size: 30, generations: 100, bloat_control, seed: Some(10), ..Config::default() };
+            let result = Engine::new(count_x(4), config).run();
+            assert_eq!(result.best.fitness, Some(0), "{:?}", parsimony);
+        }
+    }
+
+    // CountX with the number of codons used as a second objective.
+    struct CountXAndSize(CountX);
+
+    impl Problem for CountXAndSize {
+        type Fitness = MultiObjective;
+
+        fn grammar(&self) -> &Grammar {
+            &self.0.grammar
+        }
+
+        fn direction(&self) -> Direction {
+            Direction::Minimize
+        }
+
+        fn evaluate(&self, phenotype: &str) -> MultiObjective {
+            MultiObjective(vec![self.0.evaluate(phenotype) as f64, 0.0])
+        }
+
+        fn evaluate_individual(&self, individual: &Individual<MultiObjective>) -> MultiObjective {
+            let error = self.0.evaluate(individual.phenotype.as_ref().unwrap());
+            MultiObjective(vec![error as f64, individual.used_codons as f64])
+        }
+
+        fn invalid_fitness(&self) -> MultiObjective {
+            MultiObjective(vec![f64::INFINITY, f64::INFINITY])
+        }
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let rejected = |config: Config| Engine::try_new(count_x(2), config).err().unwrap();
+        assert!(matches!(rejected(Config { population_size: 0, ..Config::default() }), ConfigError::EmptyPopulation));
+        let islands = Some(IslandModel { islands: 0, ..IslandModel::default() });
+        assert!(matches!(rejected(Config { islands, ..Config::default() }), ConfigError::NoIslands));
+        for probability in [1.5, -0.1, f64::NAN] {
+            let config = Config { crossover_probability: probability, ..Config::default() };
+            assert!(matches!(rejected(config), ConfigError::CrossoverProbability(_)));
+            let config = Config { mutation_probability: probability, ..Config::default() };
+            assert!(matches!(rejected(config), ConfigError::MutationProbability(_)));
+        }
+        assert!(matches!(rejected(Config { tournament_size: 0, ..Config::default() }), ConfigError::TournamentSize(0)));
+        for rate in [0.0, 1.5, f64::NAN] {
+            let config = Config { selection: ParentSelection::DownsampledLexicase { rate }, ..Config::default() };
+            assert!(matches!(rejected(config), ConfigError::DownsampleRate(_)));
+        }
+
+        for tarpeian in [1.5, -0.1, f64::NAN] {
+            let config = Config { bloat_control: BloatControl { tarpeian: Some(tarpeian), ..BloatControl::default() }, ..Config::default() };
+            assert!(matches!(Engine::try_new(count_x(2), config), Err(ConfigError::TarpeianProbability(_))));
+        }
+        assert!(Engine::try_new(count_x(2), Config::default()).is_ok());
+        let config = Config { representation: Representation::Pge { learning_factor: 2.0 }, ..Config::default() };
+        assert!(matches!(Engine::try_new(count_x(2), config), Err(ConfigError::LearningFactor(_))));
+
+        let sge = Config { representation: Representation::Sge { max_depth: 3 }, ..Config::default() };
+        let config = Config { crossover: Crossover::Effective, ..sge.clone() };
+        assert!(matches!(Engine::try_new(count_x(2), config), Err(ConfigError::SgeCrossover(Crossover::Effective))));
+        let problem = CountX { grammar: parse_grammar_str("<e> ::= <f> | <f> + <e>\n<f> ::= ( <e> ) | x").unwrap(), target: 1 };
+        let config = Config { representation: Representation::Sge { max_depth: 1 }, ..sge };
+        assert!(matches!(Engine::try_new(problem, config), Err(ConfigError::Sge(GrammarError::RecursionTooShallow(1)))));
+    }
+
+    #[test]
+    fn test_tarpeian_victims_lose_lexicase_selection() {
+        let config = Config {
+            selection: ParentSelection::Lexicase,
+            bloat_control: BloatControl { tarpeian: Some(1.0), ..BloatControl::default() },
+            ..Config::default()
+        };
+        let engine = Engine::new(count_x(2), config);
+        let mut parents: Population<usize> = vec![Individual::new(vec![1]), Individual::new(vec![2])];
+        engine.evaluate(&mut parents, None);
+        // "x + x" solves every case and "1" none, but "x + x" is larger than the parents.
+        let mut offspring = vec![Individual::new(vec![0, 1, 1]), Individual::new(vec![2])];
+        engine.tarpeian(&mut offspring, &parents, None, &mut random::seeded(8));
+        engine.evaluate(&mut offspring, None);
+        assert_eq!(offspring[0].fitness, Some(usize::MAX));
+        let lexicase = Lexicase::new(&offspring);
+        let mut rng = random::seeded(9);
+        assert!((0..20).all(|_| lexicase.select_index(&offspring, &mut rng) == 1));
+    }
+
+    #[test]
+    fn test_nsga2() {
+        let config = Config { population_size: 30, generations: 30, replacement: Replacement::Nsga2, seed: Some(8), ..Config::default() };
+        let result = Engine::new(CountXAndSize(count_x(4)), config).run();
+        assert_eq!(result.final_population.len(), 30);
+
+        // The front trades error against size: the smallest solutions miss the target, and
+        // the exact ones are larger.
+        let front = pareto_front(&result.final_population, Direction::Minimize);
+        let objectives: Vec<Vec<f64>> = front.iter().map(|individual| individual.fitness.clone().unwrap().0).collect();
+        assert!(objectives.len() > 1);
+        assert_eq!(objectives[0][0], 0.0);
+        assert!(objectives.windows(2).all(|pair| pair[0][0] < pair[1][0] && pair[0][1] > pair[1][1]));
+    }
+
+    #[test]
+    fn test_island_model() {
+        let config = Config {
+            population_size: 10,
+            generations: 6,
+            islands: Some(IslandModel { islands: 3, migration_interval: 2, ..IslandModel::default() }),
+            seed: Some(4),
+            ..Config::default()
+        };
+        let result = Engine::new(count_x(1000), config).run();
+        assert_eq!(result.final_population.len(), 30);
+        assert_eq!(result.history.len(), 7);
+        for stats in &result.history {
+            assert_eq!(stats.islands.len(), 3);
+            assert!(stats.islands.iter().all(|island| island.best_fitness >= stats.best_fitness && island.islands.is_empty()));
+            assert_eq!(stats.invalid, stats.islands.iter().map(|island| island.invalid).sum::<usize>());
+        }
+        let mut ids: Vec<usize> = result.final_population.iter().map(|individual| individual.id).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), 30);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_evaluation_matches_serial() {
+        let serial = Engine::new(count_x(10), Config { threads: Some(1), ..Config::default() });
+        let parallel = Engine::new(count_x(10), Config { threads: Some(4), ..Config::default() });
+        let population = serial.initial_population(None, &mut IdGenerator::new(0, 1), &mut random::seeded(3));
+
+        let mut a = population.clone();
+        serial.evaluate(&mut a, None);
+        let mut b = population;
+        parallel.evaluate(&mut b, None);
+        assert_eq!(a, b);
+        assert!(a.iter().all(Individual::is_evaluated));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_islands_match_serial() {
+        let config = |threads| Config {
+            population_size: 10,
+            generations: 5,
+            islands: Some(IslandModel { islands: 4, migration_interval: 2, ..IslandModel::default() }),
+            threads: Some(threads),
+            seed: Some(5),
+            ..Config::default()
+        };
+        let serial = Engine::new(count_x(1000), config(1)).run();
+        let parallel = Engine::new(count_x(1000), config(4)).run();
+        assert_eq!(serial.history, parallel.history);
+        assert_eq!(serial.final_population, parallel.final_population);
+    }
+}
@@ -1,39 +1,535 @@
-use rand::Rng;
+use std::cmp::Ordering;
+use std::fmt;
+
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore};
+
+use crate::bloat::Size;
+use crate::fitness::{Direction, Fitness};
+use crate::individual::Individual;
 
 type Genome = Vec<usize>;
-type Population = Vec<Genome>;
 
-// Tournament Selection
-pub fn tournament_selection(population: &Population, tournament_size: usize) -> &Genome {
-    let mut rng = rand::thread_rng();
-    let mut best = &population[rng.gen_range(0..population.len())];
+// Which of several equally fit contenders wins a tournament.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TieBreak {
+    // Each tied contender is equally likely to win.
+    #[default]
+    Random,
+    // The smallest contender by the given measure, then the first one drawn.
+    Smaller(Size),
+    // The first contender drawn.
+    First,
+}
+
+// Tournament Selection. Contenders are drawn with replacement and the fittest under
+// `direction` wins; unevaluated individuals count as the worst.
+pub fn tournament_selection<'a, F: Fitness>(
+    population: &'a [Individual<F>],
+    tournament_size: usize,
+    direction: Direction,
+    tie_break: TieBreak,
+    rng: &mut impl Rng,
+) -> &'a Individual<F> {
+    &population[tournament_index(population, tournament_size, direction, tie_break, rng)]
+}
+
+// Tournament Selection returning the index of the winner.
+pub fn tournament_index<F: Fitness>(
+    population: &[Individual<F>],
+    tournament_size: usize,
+    direction: Direction,
+    tie_break: TieBreak,
+    rng: &mut impl Rng,
+) -> usize {
+    let mut best = rng.gen_range(0..population.len());
+    let mut ties = 1;
 
     for _ in 1..tournament_size {
-        let contender = &population[rng.gen_range(0..population.len())];
-        // Here the assumption is that lower genome length has better fitness.
-        // TODO: use a fitness function.
-        if contender.len() < best.len() {
-            best = contender;
+        let contender = rng.gen_range(0..population.len());
+        let ordering = match (population[contender].compare(&population[best], direction), tie_break) {
+            (Ordering::Equal, TieBreak::Smaller(size)) => size.of(&population[contender]).cmp(&size.of(&population[best])),
+            (ordering, _) => ordering,
+        };
+        match ordering {
+            Ordering::Less => {
+                best = contender;
+                ties = 1;
+            }
+            Ordering::Equal if tie_break == TieBreak::Random => {
+                ties += 1;
+                if rng.gen_range(0..ties) == 0 {
+                    best = contender;
+                }
+            }
+            _ => {}
         }
     }
 
     best
 }
 
-// One-point Crossover
-pub fn one_point_crossover(parent1: &Genome, parent2: &Genome) -> (Genome, Genome) {
-    let mut rng = rand::thread_rng();
-    let crossover_point = rng.gen_range(0..parent1.len().min(parent2.len()));
+// How the engine chooses parents.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ParentSelection {
+    // Tournaments of `Config::tournament_size`, with ties broken by `Config::tie_break`.
+    #[default]
+    Tournament,
+    // Lexicase selection on the individuals' case errors.
+    Lexicase,
+    // Lexicase selection where, on each case, every individual within epsilon of the best
+    // error passes. Each case's epsilon is the median absolute deviation of the population's
+    // errors on it.
+    EpsilonLexicase,
+    // Lexicase selection on a random fraction `rate` of the cases, drawn again for every
+    // generation.
+    DownsampledLexicase { rate: f64 },
+    // The schemes below are described at their `Selection` implementations.
+    RouletteWheel,
+    StochasticUniversalSampling,
+    LinearRank { pressure: f64 },
+    ExponentialRank { base: f64 },
+    Truncation { fraction: f64 },
+}
+
+impl ParentSelection {
+    // Whether the scheme selects on `Individual::case_errors`.
+    pub fn uses_cases(self) -> bool {
+        matches!(
+            self,
+            ParentSelection::Lexicase | ParentSelection::EpsilonLexicase | ParentSelection::DownsampledLexicase { .. }
+        )
+    }
+
+    // The scheme as a `Selection`, for the ones that are not tournament or lexicase.
+    pub fn scheme<F: Fitness>(self) -> Option<Box<dyn Selection<F>>> {
+        match self {
+            ParentSelection::RouletteWheel => Some(Box::new(RouletteWheel { stochastic_universal: false })),
+            ParentSelection::StochasticUniversalSampling => Some(Box::new(RouletteWheel { stochastic_universal: true })),
+            ParentSelection::LinearRank { pressure } => Some(Box::new(LinearRank { pressure })),
+            ParentSelection::ExponentialRank { base } => Some(Box::new(ExponentialRank { base })),
+            ParentSelection::Truncation { fraction } => Some(Box::new(Truncation { fraction })),
+            _ => None,
+        }
+    }
+}
+
+// A selection scheme that picks all the parents of a generation at once, as stochastic
+// universal sampling has to.
+pub trait Selection<F: Fitness>: fmt::Debug + Send + Sync {
+    // Indices of `count` parents, in no particular order.
+    fn select(&self, population: &[Individual<F>], count: usize, direction: Direction, rng: &mut dyn RngCore) -> Vec<usize>;
+}
+
+// Fitness-proportionate selection on the first objective of the fitness. Values are shifted so
+// that the worst individual gets weight 0 in either direction; NaN, infinite and missing
+// fitness values get weight 0 too. When all weights are 0 every individual is equally likely.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RouletteWheel {
+    // Stochastic universal sampling: one spin with `count` evenly spaced pointers, so that
+    // every individual is picked within one of its expected number of times.
+    pub stochastic_universal: bool,
+}
+
+impl<F: Fitness> Selection<F> for RouletteWheel {
+    fn select(&self, population: &[Individual<F>], count: usize, direction: Direction, rng: &mut dyn RngCore) -> Vec<usize> {
+        let values: Vec<Option<f64>> = population
+            .iter()
+            .map(|individual| {
+                let value = individual.fitness.as_ref()?.objectives().first().copied()?;
+                value.is_finite().then_some(value)
+            })
+            .collect();
+        let finite = values.iter().flatten();
+        let (min, max) = finite.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &value| (min.min(value), max.max(value)));
+        let weights: Vec<f64> = values
+            .iter()
+            .map(|value| match (value, direction) {
+                (Some(value), Direction::Maximize) => value - min,
+                (Some(value), Direction::Minimize) => max - value,
+                (None, _) => 0.0,
+            })
+            .collect();
+        sample(&weights, count, self.stochastic_universal, rng)
+    }
+}
+
+// Rank selection with weights falling linearly from `pressure` for the best individual to
+// `2 - pressure` for the worst; `pressure` is the expected number of copies of the best, from
+// 1 (no pressure) to 2.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinearRank {
+    pub pressure: f64,
+}
+
+impl<F: Fitness> Selection<F> for LinearRank {
+    fn select(&self, population: &[Individual<F>], count: usize, direction: Direction, rng: &mut dyn RngCore) -> Vec<usize> {
+        let pressure = if self.pressure.is_nan() { 1.0 } else { self.pressure.clamp(1.0, 2.0) };
+        let last = population.len().saturating_sub(1).max(1) as f64;
+        rank_select(population, direction, count, rng, |rank| pressure - 2.0 * (pressure - 1.0) * rank as f64 / last)
+    }
+}
+
+// Rank selection with the weight of rank `i` (0 for the best) proportional to `base^i`, for
+// `base` between 0 and 1; smaller bases select harder.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExponentialRank {
+    pub base: f64,
+}
+
+impl<F: Fitness> Selection<F> for ExponentialRank {
+    fn select(&self, population: &[Individual<F>], count: usize, direction: Direction, rng: &mut dyn RngCore) -> Vec<usize> {
+        let base = if self.base.is_nan() { 1.0 } else { self.base.clamp(f64::MIN_POSITIVE, 1.0) };
+        rank_select(population, direction, count, rng, |rank| base.powi(rank as i32))
+    }
+}
+
+// Parents drawn uniformly from the best `fraction` of the population, at least one individual.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Truncation {
+    pub fraction: f64,
+}
+
+impl<F: Fitness> Selection<F> for Truncation {
+    fn select(&self, population: &[Individual<F>], count: usize, direction: Direction, rng: &mut dyn RngCore) -> Vec<usize> {
+        let fraction = if self.fraction.is_nan() { 1.0 } else { self.fraction.clamp(0.0, 1.0) };
+        let kept = ((population.len() as f64 * fraction).ceil() as usize).clamp(1, population.len().max(1));
+        let ranking = ranking(population, direction);
+        (0..count).map(|_| ranking[rng.gen_range(0..kept)]).collect()
+    }
+}
+
+// Indices from best to worst. Missing fitness values and values with a NaN or infinite
+// objective rank last in either direction, as they get no weight on the roulette wheel. Ties
+// keep their order.
+fn ranking<F: Fitness>(population: &[Individual<F>], direction: Direction) -> Vec<usize> {
+    let finite: Vec<bool> = population
+        .iter()
+        .map(|individual| individual.fitness.as_ref().is_some_and(|fitness| fitness.objectives().iter().all(|value| value.is_finite())))
+        .collect();
+    let mut ranking: Vec<usize> = (0..population.len()).collect();
+    ranking.sort_by(|&a, &b| finite[b].cmp(&finite[a]).then_with(|| population[a].compare(&population[b], direction)));
+    ranking
+}
 
-    let child1: Genome = parent1[..crossover_point].iter().chain(&parent2[crossover_point..]).cloned().collect();
-    let child2: Genome = parent2[..crossover_point].iter().chain(&parent1[crossover_point..]).cloned().collect();
+fn rank_select<F: Fitness>(
+    population: &[Individual<F>],
+    direction: Direction,
+    count: usize,
+    rng: &mut dyn RngCore,
+    weight: impl Fn(usize) -> f64,
+) -> Vec<usize> {
+    let mut weights = vec![0.0; population.len()];
+    for (rank, index) in ranking(population, direction).into_iter().enumerate() {
+        weights[index] = weight(rank);
+    }
+    sample(&weights, count, false, rng)
+}
+
+// `count` indices drawn with probability proportional to `weights`, by roulette wheel or
+// stochastic universal sampling; uniformly if no weight is positive.
+fn sample(weights: &[f64], count: usize, stochastic_universal: bool, rng: &mut dyn RngCore) -> Vec<usize> {
+    let total: f64 = weights.iter().filter(|weight| weight.is_finite() && **weight > 0.0).sum();
+    if weights.is_empty() {
+        return Vec::new();
+    }
+    if !(total > 0.0 && total.is_finite()) {
+        return (0..count).map(|_| rng.gen_range(0..weights.len())).collect();
+    }
+
+    let mut cumulative = Vec::with_capacity(weights.len());
+    let mut sum = 0.0;
+    for weight in weights {
+        if weight.is_finite() && *weight > 0.0 {
+            sum += weight;
+        }
+        cumulative.push(sum);
+    }
+    // The first index whose cumulative weight exceeds `point`, skipping zero weights.
+    let find = |point: f64| cumulative.partition_point(|&sum| sum <= point).min(weights.len() - 1);
+
+    if stochastic_universal {
+        let step = total / count as f64;
+        let start = rng.gen_range(0.0..step);
+        (0..count).map(|i| find(start + i as f64 * step)).collect()
+    } else {
+        (0..count).map(|_| find(rng.gen_range(0.0..total))).collect()
+    }
+}
+
+// The cases lexicase selection filters on in one generation, and for epsilon-lexicase their
+// epsilons. Case errors are taken from `Individual::case_errors`, with missing, NaN and
+// individuals without errors counting as infinitely bad; fitness and direction play no part.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lexicase {
+    pub cases: Vec<usize>,
+    pub epsilons: Option<Vec<f64>>,
+}
+
+impl Lexicase {
+    // Every case any individual in the population has an error for.
+    pub fn new<F: Fitness>(population: &[Individual<F>]) -> Self {
+        let count = population.iter().filter_map(|individual| individual.case_errors.as_ref()).map(Vec::len).max().unwrap_or(0);
+        Lexicase { cases: (0..count).collect(), epsilons: None }
+    }
+
+    pub fn epsilon<F: Fitness>(population: &[Individual<F>]) -> Self {
+        let cases = Lexicase::new(population).cases;
+        let epsilons = cases
+            .iter()
+            .map(|&case| {
+                let errors: Vec<f64> = population.iter().map(|individual| case_error(individual, case)).filter(|error| error.is_finite()).collect();
+                median_absolute_deviation(&errors)
+            })
+            .collect();
+        Lexicase { cases, epsilons: Some(epsilons) }
+    }
 
+    // A random `rate` of the population's cases, at least one if there are any.
+    pub fn downsampled<F: Fitness>(population: &[Individual<F>], rate: f64, rng: &mut impl Rng) -> Self {
+        let cases = Lexicase::new(population).cases;
+        let count = ((cases.len() as f64 * rate).ceil() as usize).clamp(cases.len().min(1), cases.len());
+        let mut sample = rand::seq::index::sample(rng, cases.len(), count).into_vec();
+        sample.sort_unstable();
+        Lexicase { cases: sample, epsilons: None }
+    }
+
+    // Index of the winner: the cases are visited in random order, each keeping only the
+    // candidates with the best error on it (within epsilon), until one candidate is left or
+    // the cases run out. Remaining ties are broken at random.
+    pub fn select_index<F: Fitness>(&self, population: &[Individual<F>], rng: &mut impl Rng) -> usize {
+        let mut candidates: Vec<usize> = (0..population.len()).collect();
+        let mut order: Vec<usize> = (0..self.cases.len()).collect();
+        order.shuffle(rng);
+
+        for i in order {
+            if candidates.len() == 1 {
+                break;
+            }
+            let case = self.cases[i];
+            let epsilon = self.epsilons.as_ref().map_or(0.0, |epsilons| epsilons[i]);
+            let best = candidates.iter().map(|&index| case_error(&population[index], case)).fold(f64::INFINITY, f64::min);
+            candidates.retain(|&index| case_error(&population[index], case) <= best + epsilon);
+        }
+        candidates[rng.gen_range(0..candidates.len())]
+    }
+}
+
+fn case_error<F>(individual: &Individual<F>, case: usize) -> f64 {
+    match individual.case_errors.as_ref().and_then(|errors| errors.get(case)) {
+        Some(error) if !error.is_nan() => *error,
+        _ => f64::INFINITY,
+    }
+}
+
+// Median of the absolute deviations from the median, or 0 for no values.
+pub fn median_absolute_deviation(values: &[f64]) -> f64 {
+    fn median(mut values: Vec<f64>) -> f64 {
+        if values.is_empty() {
+            return 0.0;
+        }
+        values.sort_by(f64::total_cmp);
+        let middle = values.len() / 2;
+        if values.len().is_multiple_of(2) { (values[middle - 1] + values[middle]) / 2.0 } else { values[middle] }
+    }
+    let center = median(values.to_vec());
+    median(values.iter().map(|value| (value - center).abs()).collect())
+}
+
+// Which crossover the engine applies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Crossover {
+    // One cut point anywhere in the shorter genome, the same in both parents.
+    #[default]
+    OnePoint,
+    // One cut point, the same in both parents, within the codons both parents used.
+    Effective,
+    // An independent cut point in each parent, so offspring lengths vary.
+    VariableLength,
+    // An independent cut point in each parent, within the codons that parent used.
+    EffectiveVariableLength,
+}
+
+impl Crossover {
+    // The two offspring genomes. Parents that have not been mapped count as using their whole
+    // genome. A parent with an empty genome leaves both genomes unchanged.
+    pub fn apply<F: Fitness>(self, parent1: &Individual<F>, parent2: &Individual<F>, rng: &mut impl Rng) -> (Genome, Genome) {
+        let (genome1, genome2) = (&parent1.genome, &parent2.genome);
+        if genome1.is_empty() || genome2.is_empty() {
+            return (genome1.clone(), genome2.clone());
+        }
+        match self {
+            Crossover::OnePoint => one_point_crossover(genome1, genome2, rng),
+            Crossover::Effective => {
+                let point = rng.gen_range(1..=effective_length(parent1).min(effective_length(parent2)));
+                swap_tails(genome1, genome2, point, point)
+            }
+            Crossover::VariableLength => variable_one_point_crossover(genome1, genome2, rng),
+            Crossover::EffectiveVariableLength => {
+                let point1 = rng.gen_range(1..=effective_length(parent1));
+                let point2 = rng.gen_range(1..=effective_length(parent2));
+                swap_tails(genome1, genome2, point1, point2)
+            }
+        }
+    }
+}
+
+// Codons of the genome that took part in the mapping: the used codons, capped at the genome
+// length when the mapper wrapped, or the whole genome when the individual has not been mapped.
+pub fn effective_length<F: Fitness>(individual: &Individual<F>) -> usize {
+    if individual.is_mapped() && individual.used_codons > 0 {
+        individual.used_codons.min(individual.genome.len())
+    } else {
+        individual.genome.len()
+    }
+}
+
+fn swap_tails(parent1: &Genome, parent2: &Genome, point1: usize, point2: usize) -> (Genome, Genome) {
+    let child1: Genome = parent1[..point1].iter().chain(&parent2[point2..]).cloned().collect();
+    let child2: Genome = parent2[..point2].iter().chain(&parent1[point1..]).cloned().collect();
     (child1, child2)
 }
 
+// One-point Crossover. Parents are returned unchanged if either is empty.
+pub fn one_point_crossover(parent1: &Genome, parent2: &Genome, rng: &mut impl Rng) -> (Genome, Genome) {
+    let length = parent1.len().min(parent2.len());
+    if length == 0 {
+        return (parent1.clone(), parent2.clone());
+    }
+    let crossover_point = rng.gen_range(0..length);
+    swap_tails(parent1, parent2, crossover_point, crossover_point)
+}
+
+// One-point crossover with an independent cut point anywhere in each parent.
+pub fn variable_one_point_crossover(parent1: &Genome, parent2: &Genome, rng: &mut impl Rng) -> (Genome, Genome) {
+    let point1 = rng.gen_range(0..=parent1.len());
+    let point2 = rng.gen_range(0..=parent2.len());
+    swap_tails(parent1, parent2, point1, point2)
+}
+
 // Mutation
-pub fn mutate(genome: &mut Genome) {
-    let mut rng = rand::thread_rng();
+pub fn mutate(genome: &mut Genome, rng: &mut impl Rng) {
     let index = rng.gen_range(0..genome.len());
     genome[index] = rng.gen_range(0..256);
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random;
+
+    #[test]
+    fn test_tournament_selection() {
+        let mut rng = random::seeded(1);
+        let fitness = [Some(4.0), Some(1.0), Some(1.0), None];
+        let population: Vec<Individual> = [3, 2, 1, 2]
+            .iter()
+            .zip(fitness)
+            .map(|(&length, fitness)| Individual { fitness, ..Individual::new(vec![0; length]) })
+            .collect();
+
+        // A tournament as large as the population almost surely sees every individual.
+        for _ in 0..20 {
+            let winner = tournament_selection(&population, 100, Direction::Maximize, TieBreak::First, &mut rng);
+            assert_eq!(winner, &population[0]);
+            let winner = tournament_selection(&population, 100, Direction::Minimize, TieBreak::Smaller(Size::GenomeLength), &mut rng);
+            assert_eq!(winner, &population[2]);
+        }
+
+        let winners: Vec<usize> = (0..200)
+            .map(|_| tournament_index(&population, 100, Direction::Minimize, TieBreak::Random, &mut rng))
+            .collect();
+        assert!(winners.contains(&1) && winners.contains(&2));
+        assert!(winners.iter().all(|&winner| winner == 1 || winner == 2));
+    }
+
+    #[test]
+    fn test_lexicase_selection() {
+        let mut rng = random::seeded(2);
+        let population: Vec<Individual> = [Some(vec![0.0, 5.0, 1.0]), Some(vec![1.0, 0.0, 1.0]), Some(vec![0.1, 0.2, 9.0]), None]
+            .into_iter()
+            .map(|case_errors| Individual { case_errors, ..Individual::new(Vec::new()) })
+            .collect();
+
+        // Each of the first two is the only one best on some case; the others never win.
+        let lexicase = Lexicase::new(&population);
+        assert_eq!(lexicase.cases, vec![0, 1, 2]);
+        let winners: Vec<usize> = (0..100).map(|_| lexicase.select_index(&population, &mut rng)).collect();
+        assert!(winners.contains(&0) && winners.contains(&1));
+        assert!(winners.iter().all(|&winner| winner < 2));
+
+        // With epsilon, the third is close enough to the best on the first two cases.
+        let lexicase = Lexicase::epsilon(&population);
+        assert_eq!(lexicase.epsilons, Some(vec![0.1, 0.2, 0.0]));
+        let winners: Vec<usize> = (0..100).map(|_| lexicase.select_index(&population, &mut rng)).collect();
+        assert!(winners.contains(&2) && !winners.contains(&3));
+
+        let lexicase = Lexicase::downsampled(&population, 0.5, &mut rng);
+        assert_eq!(lexicase.cases.len(), 2);
+        assert_eq!(Lexicase::downsampled(&population, 0.01, &mut rng).cases.len(), 1);
+        assert_eq!(median_absolute_deviation(&[1.0, 2.0, 3.0, 4.0, 100.0]), 1.0);
+    }
+
+    #[test]
+    fn test_selection_schemes() {
+        let mut rng = random::seeded(3);
+        let fitness = [Some(1.0), Some(2.0), Some(4.0), Some(f64::NAN), None, Some(f64::INFINITY)];
+        let population: Vec<Individual> = fitness.into_iter().map(|fitness| Individual { fitness, ..Individual::new(Vec::new()) }).collect();
+        let counts = |selected: Vec<usize>| -> Vec<usize> { (0..population.len()).map(|i| selected.iter().filter(|&&j| j == i).count()).collect() };
+
+        // Weights 0, 1 and 3 when maximising, and 3, 2 and 0 when minimising.
+        let sus = RouletteWheel { stochastic_universal: true };
+        assert_eq!(counts(sus.select(&population, 8, Direction::Maximize, &mut rng)), vec![0, 2, 6, 0, 0, 0]);
+        let selected = counts(RouletteWheel::default().select(&population, 1000, Direction::Minimize, &mut rng));
+        assert!(selected[0] > selected[1] && selected[2..].iter().all(|&count| count == 0));
+
+        let selected = counts(LinearRank { pressure: 2.0 }.select(&population, 1000, Direction::Minimize, &mut rng));
+        assert!(selected.windows(2).take(2).all(|pair| pair[0] > pair[1]));
+        // The worst-ranked individual (unevaluated) has weight 0.
+        assert_eq!(selected[4], 0);
+        // Infinite fitness ranks after every finite value in either direction.
+        let selected = counts(ExponentialRank { base: 0.5 }.select(&population, 1000, Direction::Maximize, &mut rng));
+        assert!(selected[2] > selected[1] && selected[1] > selected[0] && selected[0] > selected[5]);
+        assert_eq!(ranking(&population, Direction::Maximize), vec![2, 1, 0, 5, 3, 4]);
+        assert_eq!(ranking(&population, Direction::Minimize), vec![0, 1, 2, 5, 3, 4]);
+
+        let selected = counts(Truncation { fraction: 0.3 }.select(&population, 100, Direction::Minimize, &mut rng));
+        assert_eq!(selected[0] + selected[1], 100);
+        let scheme: Box<dyn Selection<f64>> = ParentSelection::Truncation { fraction: 0.0 }.scheme().unwrap();
+        assert_eq!(counts(scheme.select(&population, 10, Direction::Maximize, &mut rng))[2], 10);
+        let minus_infinity: Vec<Individual> =
+            [Some(f64::NEG_INFINITY), Some(3.0)].into_iter().map(|fitness| Individual { fitness, ..Individual::new(Vec::new()) }).collect();
+        assert_eq!(scheme.select(&minus_infinity, 10, Direction::Minimize, &mut rng), vec![1; 10]);
+    }
+
+    #[test]
+    fn test_crossover() {
+        let mut rng = random::seeded(5);
+        let parent = |genome: Genome, used_codons: usize| -> Individual {
+            Individual { phenotype: Some(String::new()), used_codons, ..Individual::new(genome) }
+        };
+        let a = parent(vec![1; 10], 3);
+        let b = parent(vec![2; 8], 20);
+        let empty = parent(Vec::new(), 0);
+        assert_eq!((effective_length(&a), effective_length(&b)), (3, 8));
+        assert_eq!(effective_length(&Individual::<f64>::new(vec![0; 5])), 5);
+        assert_eq!(one_point_crossover(&a.genome, &empty.genome, &mut rng), (a.genome.clone(), Vec::new()));
+
+        for _ in 0..50 {
+            // The cut is inside the three codons `a` used.
+            let (child1, child2) = Crossover::Effective.apply(&a, &b, &mut rng);
+            let point = child1.iter().filter(|&&codon| codon == 1).count();
+            assert!((1..=3).contains(&point));
+            assert_eq!((child1.len(), child2.len()), (8, 10));
+
+            let (child1, child2) = Crossover::EffectiveVariableLength.apply(&a, &b, &mut rng);
+            assert!((1..=3).contains(&child1.iter().filter(|&&codon| codon == 1).count()));
+            assert!((1..=8).contains(&child2.iter().filter(|&&codon| codon == 2).count()));
+            assert_eq!(child1.len() + child2.len(), 18);
+
+            let (child1, child2) = Crossover::VariableLength.apply(&a, &b, &mut rng);
+            assert_eq!(child1.len() + child2.len(), 18);
+            assert_eq!(Crossover::Effective.apply(&empty, &b, &mut rng), (Vec::new(), b.genome.clone()));
+        }
+    }
+}
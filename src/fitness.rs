@@ -0,0 +1,188 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+// Whether lower or higher fitness values are better.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Direction {
+    #[default]
+    Minimize,
+    Maximize,
+}
+
+impl Direction {
+    // Turns an ascending ordering of raw values into a better-first one.
+    pub fn orient(self, ascending: Ordering) -> Ordering {
+        match self {
+            Direction::Minimize => ascending,
+            Direction::Maximize => ascending.reverse(),
+        }
+    }
+}
+
+// A fitness value. `compare` is a total order with `Ordering::Less` meaning `self` is better
+// under `direction`; it is what selection, elitism and reporting sort by. Values that cannot
+// be compared meaningfully, such as NaN, are worse than everything else.
+pub trait Fitness: Clone + PartialEq + fmt::Debug + Send + Sync {
+    fn compare(&self, other: &Self, direction: Direction) -> Ordering;
+
+    // Pareto dominance: at least as good in every objective and better in one. For a single
+    // objective this is just being better.
+    fn dominates(&self, other: &Self, direction: Direction) -> bool {
+        self.compare(other, direction) == Ordering::Less
+    }
+
+    // The value as numbers, one per objective, for measuring how far apart two fitness values
+    // are (e.g. the crowding distance of NSGA-II).
+    fn objectives(&self) -> Vec<f64>;
+}
+
+macro_rules! float_fitness {
+    ($($t:ty),*) => {$(
+        impl Fitness for $t {
+            fn compare(&self, other: &Self, direction: Direction) -> Ordering {
+                match (self.is_nan(), other.is_nan()) {
+                    (true, true) => Ordering::Equal,
+                    (true, false) => Ordering::Greater,
+                    (false, true) => Ordering::Less,
+                    (false, false) => direction.orient(self.partial_cmp(other).unwrap()),
+                }
+            }
+
+            fn objectives(&self) -> Vec<f64> {
+                vec![*self as f64]
+            }
+        }
+    )*};
+}
+
+macro_rules! integer_fitness {
+    ($($t:ty),*) => {$(
+        impl Fitness for $t {
+            fn compare(&self, other: &Self, direction: Direction) -> Ordering {
+                direction.orient(self.cmp(other))
+            }
+
+            fn objectives(&self) -> Vec<f64> {
+                vec![*self as f64]
+            }
+        }
+    )*};
+}
+
+float_fitness!(f32, f64);
+integer_fitness!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+// Lexicographic fitness: later components only break ties in earlier ones, e.g. error then
+// size. Every component is optimised in the same direction; negate a component to optimise it
+// the other way.
+macro_rules! tuple_fitness {
+    ($(($($name:ident $index:tt),*)),*) => {$(
+        impl<$($name: Fitness),*> Fitness for ($($name,)*) {
+            fn compare(&self, other: &Self, direction: Direction) -> Ordering {
+                Ordering::Equal $(.then_with(|| self.$index.compare(&other.$index, direction)))*
+            }
+
+            fn objectives(&self) -> Vec<f64> {
+                [$(self.$index.objectives()),*].concat()
+            }
+        }
+    )*};
+}
+
+tuple_fitness!((A 0, B 1), (A 0, B 1, C 2), (A 0, B 1, C 2, D 3));
+
+// Several objectives optimised at once, all in the same direction. `compare` orders the
+// objectives lexicographically, which never ranks a dominated value above one dominating it;
+// multi-objective selection should use `dominates`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MultiObjective(pub Vec<f64>);
+
+impl Fitness for MultiObjective {
+    fn compare(&self, other: &Self, direction: Direction) -> Ordering {
+        self.0
+            .iter()
+            .zip(&other.0)
+            .map(|(a, b)| a.compare(b, direction))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+
+    fn dominates(&self, other: &Self, direction: Direction) -> bool {
+        let mut better = false;
+        for (a, b) in self.0.iter().zip(&other.0) {
+            match a.compare(b, direction) {
+                Ordering::Greater => return false,
+                Ordering::Less => better = true,
+                Ordering::Equal => {}
+            }
+        }
+        better
+    }
+
+    fn objectives(&self) -> Vec<f64> {
+        self.0.clone()
+    }
+}
+
+impl fmt::Display for MultiObjective {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(")?;
+        for (i, objective) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", objective)?;
+        }
+        write!(f, ")")
+    }
+}
+
+// Compares optional fitness values, with `None` (not yet evaluated) as the worst.
+pub fn compare_optional<F: Fitness>(a: Option<&F>, b: Option<&F>, direction: Direction) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.compare(b, direction),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scalar_fitness() {
+        assert_eq!(1.0.compare(&2.0, Direction::Minimize), Ordering::Less);
+        assert_eq!(1.0.compare(&2.0, Direction::Maximize), Ordering::Greater);
+        assert_eq!(f64::NAN.compare(&f64::NEG_INFINITY, Direction::Maximize), Ordering::Greater);
+        assert_eq!(f64::NAN.compare(&f64::INFINITY, Direction::Minimize), Ordering::Greater);
+        assert_eq!(3i32.compare(&-2, Direction::Maximize), Ordering::Less);
+        assert!(5usize.dominates(&4, Direction::Maximize));
+        assert_eq!(compare_optional(None, Some(&f64::NAN), Direction::Minimize), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_lexicographic_fitness() {
+        // Equal error, so the smaller size wins.
+        assert_eq!((0.5, 10).compare(&(0.5, 12), Direction::Minimize), Ordering::Less);
+        assert_eq!((0.4, 100).compare(&(0.5, 12), Direction::Minimize), Ordering::Less);
+        assert_eq!((0.5, 10, 1u8).compare(&(0.5, 10, 1), Direction::Minimize), Ordering::Equal);
+        assert_eq!((f64::NAN, 1).compare(&(1e9, 1000), Direction::Minimize), Ordering::Greater);
+        assert_eq!((0.5, 10, 1u8).objectives(), vec![0.5, 10.0, 1.0]);
+    }
+
+    #[test]
+    fn test_multi_objective_fitness() {
+        let a = MultiObjective(vec![1.0, 2.0]);
+        let b = MultiObjective(vec![2.0, 2.0]);
+        let c = MultiObjective(vec![0.0, 3.0]);
+        assert!(a.dominates(&b, Direction::Minimize));
+        assert!(!a.dominates(&c, Direction::Minimize) && !c.dominates(&a, Direction::Minimize));
+        assert!(b.dominates(&a, Direction::Maximize));
+        assert!(!a.dominates(&a, Direction::Minimize));
+        assert_eq!(c.compare(&a, Direction::Minimize), Ordering::Less);
+        assert!(!MultiObjective(vec![f64::NAN, 0.0]).dominates(&b, Direction::Minimize));
+        assert_eq!(a.to_string(), "(1, 2)");
+    }
+}
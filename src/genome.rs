@@ -1,25 +1,21 @@
+use rand::Rng;
+
 // Define the Genome type as a vector of integers
 pub type Genome = Vec<usize>;
 
 // Constants for genome parameters
 const MAX_GENOME_LENGTH: usize = 100;
-const MAX_GENE_VALUE: usize = 255;
+pub const MAX_GENE_VALUE: usize = 255;
 
 // Function to initialize a random genome
-pub fn initialize_genome() -> Genome {
-    use rand::Rng;
-    let mut rng = rand::thread_rng();
-    
+pub fn initialize_genome(rng: &mut impl Rng) -> Genome {
     let length = rng.gen_range(1..=MAX_GENOME_LENGTH);
     (0..length).map(|_| rng.gen_range(0..=MAX_GENE_VALUE)).collect()
 }
 
 
 // Function to extend a genome
-pub fn extend_genome(genome: &mut Genome, extension_length: usize) {
-    use rand::Rng;
-    let mut rng = rand::thread_rng();
-    
+pub fn extend_genome(genome: &mut Genome, extension_length: usize, rng: &mut impl Rng) {
     for _ in 0..extension_length {
         genome.push(rng.gen_range(0..=MAX_GENE_VALUE));
     }
@@ -1,123 +1,1146 @@
 use std::collections::HashMap;
-use std::fs::{File, read_to_string};
-use std::io::{self, BufRead};
-use std::path::Path;
-use lazy_static::lazy_static;
+use std::fmt;
+use std::fs::read_to_string;
+use std::io;
+use std::str::FromStr;
 
-pub type Grammar = HashMap<String, Vec<String>>;
-pub type Genome = Vec<i32>;
+// A symbol on the right-hand side of a production, exactly as written in the BNF source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BnfSymbol {
+    Terminal(String),
+    NonTerminal(String),
+}
 
-lazy_static! {
-    static ref GRAMMAR: HashMap<&'static str, Vec<&'static str>> = {
-        let mut map = HashMap::new();
-        map.insert("S", vec!["E"]);
-        map.insert("E", vec!["E OR T", "T"]);
-        map.insert("T", vec!["T AND F", "F"]);
-        map.insert("F", vec!["NOT F", "A", "B", "C"]);
-        map
-    };
+impl BnfSymbol {
+    pub fn text(&self) -> &str {
+        match self {
+            BnfSymbol::Terminal(text) | BnfSymbol::NonTerminal(text) => text,
+        }
+    }
 }
 
-pub fn parse_grammar(filename: &str) -> io::Result<Grammar> {
-    let path = Path::new(filename);
-    let file = File::open(&path)?;
-    let reader = io::BufReader::new(file);
+// One alternative of a rule, with the weight written after it as `[0.3]`, if any.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BnfProduction {
+    pub symbols: Vec<BnfSymbol>,
+    pub weight: Option<f64>,
+}
 
-    let mut grammar = Grammar::new();
+// One `<name> ::= ...` rule. `line` and `column` point at the left-hand side.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BnfRule {
+    pub name: String,
+    pub productions: Vec<BnfProduction>,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    // A quoted terminal is not closed before the end of the line.
+    UnterminatedString,
+    // Something other than `<name>` appears before `::=`.
+    InvalidLeftHandSide(String),
+    // `::=` with nothing in front of it.
+    MissingLeftHandSide,
+    // Symbols or `|` appear before the first rule.
+    ExpectedRule,
+    // A production weight that is not a finite, non-negative number.
+    InvalidWeight(String),
+    // A weight that does not end its production.
+    MisplacedWeight(String),
+}
+
+// Syntax error in a BNF source. Lines and columns are 1-based; columns count characters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
 
-    for line in reader.lines() {
-        let line = line?;
-        let parts: Vec<&str> = line.split("::=").collect();
-        if parts.len() == 2 {
-            let non_terminal = parts[0].trim().to_string();
-            let productions: Vec<String> = parts[1].split('|').map(|s| s.trim().to_string()).collect();
-            grammar.insert(non_terminal, productions);
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+        match &self.kind {
+            ParseErrorKind::UnterminatedString => write!(f, "unterminated quoted terminal"),
+            ParseErrorKind::InvalidLeftHandSide(text) => {
+                write!(f, "left-hand side `{}` must be a non-terminal such as `<{}>`", text, text)
+            }
+            ParseErrorKind::MissingLeftHandSide => write!(f, "`::=` without a left-hand side"),
+            ParseErrorKind::ExpectedRule => write!(f, "expected a rule of the form `<name> ::= ...`"),
+            ParseErrorKind::InvalidWeight(text) => {
+                write!(f, "invalid production weight `{}` (quote it if it is meant as a terminal)", text)
+            }
+            ParseErrorKind::MisplacedWeight(text) => {
+                write!(f, "weight `{}` must end its production (quote it if it is meant as a terminal)", text)
+            }
         }
     }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug)]
+pub enum GrammarError {
+    Io(io::Error),
+    Parse(ParseError),
+    // The source contains no rules.
+    Empty,
+    // The requested start symbol has no rule.
+    UndefinedStart(String),
+    // `Grammar::validate` found problems.
+    Invalid(Vec<Diagnostic>),
+    // Unrolling recursive rules to this depth leaves the start symbol without productions.
+    RecursionTooShallow(usize),
+}
+
+impl fmt::Display for GrammarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GrammarError::Io(e) => write!(f, "{}", e),
+            GrammarError::Parse(e) => write!(f, "{}", e),
+            GrammarError::Empty => write!(f, "grammar has no rules"),
+            GrammarError::UndefinedStart(name) => write!(f, "start symbol <{}> is not defined", name),
+            GrammarError::RecursionTooShallow(depth) => {
+                write!(f, "no derivation of the start symbol fits within {} levels of recursion", depth)
+            }
+            GrammarError::Invalid(diagnostics) => {
+                write!(f, "invalid grammar:")?;
+                for diagnostic in diagnostics {
+                    write!(f, "\n  {}", diagnostic)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for GrammarError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GrammarError::Io(e) => Some(e),
+            GrammarError::Parse(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for GrammarError {
+    fn from(e: io::Error) -> Self {
+        GrammarError::Io(e)
+    }
+}
+
+impl From<ParseError> for GrammarError {
+    fn from(e: ParseError) -> Self {
+        GrammarError::Parse(e)
+    }
+}
 
-    Ok(grammar)
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    NonTerminal(String),
+    Terminal(String),
+    // A bare `[...]`, which is always a production weight.
+    Weight(String),
+    Define,
+    Pipe,
 }
 
-pub type Grammar1 = HashMap<String, Vec<Vec<String>>>;
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    line: usize,
+    column: usize,
+}
+
+// Length of the `<name>` starting at `chars[0]`, if there is one. A `<` that does not open
+// a well-formed non-terminal (e.g. `<=` or `< 3`) is read as part of a terminal instead.
+fn non_terminal_len(chars: &[char]) -> Option<usize> {
+    if chars.first() != Some(&'<') {
+        return None;
+    }
+    let close = chars[1..].iter().position(|&c| c == '>' || c == '<' || c.is_whitespace())? + 1;
+    if chars[close] == '>' && close > 1 {
+        Some(close + 1)
+    } else {
+        None
+    }
+}
+
+fn starts_with_define(chars: &[char]) -> bool {
+    chars.starts_with(&[':', ':', '='])
+}
+
+fn ends_bare_terminal(chars: &[char]) -> bool {
+    let c = chars[0];
+    c.is_whitespace()
+        || matches!(c, '|' | '"' | '\'')
+        || starts_with_define(chars)
+        || non_terminal_len(chars).is_some()
+}
+
+// Bare tokens in square brackets are reserved for weights, so that a weight can never be
+// mistaken for a terminal or the other way round.
+fn looks_like_weight(text: &str) -> bool {
+    text.len() >= 2 && text.starts_with('[') && text.ends_with(']')
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+
+    for (line_index, text) in source.lines().enumerate() {
+        let line = line_index + 1;
+        let chars: Vec<char> = text.chars().collect();
+        let mut i = 0;
+
+        while i < chars.len() {
+            let c = chars[i];
+            let column = i + 1;
+            let rest = &chars[i..];
+
+            let kind = if c.is_whitespace() {
+                i += 1;
+                continue;
+            } else if c == '#' {
+                break;
+            } else if c == '|' {
+                i += 1;
+                TokenKind::Pipe
+            } else if starts_with_define(rest) {
+                i += 3;
+                TokenKind::Define
+            } else if c == '"' || c == '\'' {
+                let len = rest[1..].iter().position(|&d| d == c).ok_or(ParseError {
+                    line,
+                    column,
+                    kind: ParseErrorKind::UnterminatedString,
+                })?;
+                i += len + 2;
+                TokenKind::Terminal(rest[1..=len].iter().collect())
+            } else if let Some(len) = non_terminal_len(rest) {
+                i += len;
+                TokenKind::NonTerminal(rest[1..len - 1].iter().collect())
+            } else {
+                let start = i;
+                i += 1;
+                while i < chars.len() && !ends_bare_terminal(&chars[i..]) {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                if looks_like_weight(&text) {
+                    TokenKind::Weight(text)
+                } else {
+                    TokenKind::Terminal(text)
+                }
+            };
+
+            tokens.push(Token { kind, line, column });
+        }
+    }
+
+    Ok(tokens)
+}
+
+// Parses BNF source text into its rules, in the order they appear.
+//
+// Non-terminals are written `<name>`. Terminals are either quoted with `"` or `'` (which
+// lets them contain spaces, `|`, `::=` or `#`) or bare runs of non-whitespace characters.
+// A production may continue over several lines, `#` starts a comment that runs to the end
+// of the line, and blank lines are ignored. A production can end with a weight in square
+// brackets, used by probabilistic mapping. Bare tokens in square brackets are always weights
+// and must end their production; a terminal such as `[0]` has to be quoted. `Grammar::validate`
+// requires either all or none of a rule's productions to have a weight.
+//
+//     # arithmetic over five inputs
+//     <e> ::= <e> + <e> [0.4]
+//           | <e> * <e> [0.1]
+//           | x[0] | x[1] | "|x|"
+pub fn parse_bnf(source: &str) -> Result<Vec<BnfRule>, ParseError> {
+    let tokens = tokenize(source)?;
+    let mut rules: Vec<BnfRule> = Vec::new();
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let token = &tokens[i];
+        let defines_next = matches!(tokens.get(i + 1), Some(Token { kind: TokenKind::Define, .. }));
+
+        match &token.kind {
+            TokenKind::NonTerminal(name) if defines_next => {
+                rules.push(BnfRule {
+                    name: name.clone(),
+                    productions: vec![BnfProduction { symbols: Vec::new(), weight: None }],
+                    line: token.line,
+                    column: token.column,
+                });
+                i += 2;
+                continue;
+            }
+            TokenKind::Terminal(text) | TokenKind::Weight(text) if defines_next => {
+                return Err(ParseError {
+                    line: token.line,
+                    column: token.column,
+                    kind: ParseErrorKind::InvalidLeftHandSide(text.clone()),
+                });
+            }
+            TokenKind::Define => {
+                return Err(ParseError {
+                    line: token.line,
+                    column: token.column,
+                    kind: ParseErrorKind::MissingLeftHandSide,
+                });
+            }
+            _ => {}
+        }
+
+        let rule = rules.last_mut().ok_or(ParseError {
+            line: token.line,
+            column: token.column,
+            kind: ParseErrorKind::ExpectedRule,
+        })?;
+        let production = rule.productions.last_mut().unwrap();
+        match &token.kind {
+            TokenKind::Pipe => rule.productions.push(BnfProduction { symbols: Vec::new(), weight: None }),
+            TokenKind::NonTerminal(name) => production.symbols.push(BnfSymbol::NonTerminal(name.clone())),
+            TokenKind::Terminal(text) => production.symbols.push(BnfSymbol::Terminal(text.clone())),
+            TokenKind::Weight(text) => {
+                let ends_production = match tokens.get(i + 1).map(|next| &next.kind) {
+                    None | Some(TokenKind::Pipe) => true,
+                    Some(TokenKind::NonTerminal(_)) => {
+                        matches!(tokens.get(i + 2), Some(Token { kind: TokenKind::Define, .. }))
+                    }
+                    _ => false,
+                };
+                if !ends_production {
+                    return Err(ParseError {
+                        line: token.line,
+                        column: token.column,
+                        kind: ParseErrorKind::MisplacedWeight(text.clone()),
+                    });
+                }
+                match text[1..text.len() - 1].trim().parse::<f64>() {
+                    Ok(weight) if weight.is_finite() && weight >= 0.0 => production.weight = Some(weight),
+                    _ => {
+                        return Err(ParseError {
+                            line: token.line,
+                            column: token.column,
+                            kind: ParseErrorKind::InvalidWeight(text.clone()),
+                        })
+                    }
+                }
+            }
+            TokenKind::Define => unreachable!(),
+        }
+        i += 1;
+    }
+
+    Ok(rules)
+}
 
-pub fn read_grammar_from_file(filename: &str) -> Result<Grammar1, std::io::Error> {
+fn read_bnf_file(filename: &str) -> Result<Vec<BnfRule>, GrammarError> {
     let content = read_to_string(filename)?;
+    Ok(parse_bnf(&content)?)
+}
+
+// A symbol in a production. Terminals index `Grammar::terminal`, non-terminals index
+// `Grammar::rule`, so mapping never has to hash or compare strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Symbol {
+    Terminal(usize),
+    NonTerminal(usize),
+}
 
-    let mut grammar: HashMap<String, Vec<Vec<String>>> = HashMap::new();
+#[derive(Debug, Clone, PartialEq)]
+pub struct Production {
+    pub symbols: Vec<Symbol>,
+    // Probability of the production under probabilistic mapping. The weights of a rule sum
+    // to 1; productions without a declared weight share whatever the declared ones leave.
+    pub weight: f64,
+    // Number of non-terminals among `symbols`.
+    pub arity: usize,
+    // Whether one of the non-terminals can derive the rule this production belongs to.
+    pub recursive: bool,
+    // Depth of the shallowest derivation tree rooted at this production, counting the rule
+    // it belongs to as depth 1. `None` if it cannot derive a string of terminals.
+    pub min_depth: Option<usize>,
+}
 
-    for line in content.lines() {
-        let parts: Vec<&str> = line.split("::=").collect();
-        if parts.len() != 2 {
-            continue;
+// A non-terminal and its productions. A non-terminal that is referenced but never defined
+// has no productions; its `line` and `column` point at the rule that first refers to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub name: String,
+    pub productions: Vec<Production>,
+    pub line: usize,
+    pub column: usize,
+    // Whether any production is recursive.
+    pub recursive: bool,
+    // Strongly connected component of the rule in the "uses non-terminal" graph. Rules
+    // share a component exactly when each can derive the other.
+    pub component: usize,
+    // Smallest `min_depth` among the productions.
+    pub min_depth: Option<usize>,
+}
+
+impl Rule {
+    pub fn is_defined(&self) -> bool {
+        !self.productions.is_empty()
+    }
+}
+
+// A context-free grammar with interned symbols. Rules keep the order of the source file and
+// the start symbol defaults to the first rule.
+#[derive(Debug, Clone)]
+pub struct Grammar {
+    rules: Vec<Rule>,
+    terminals: Vec<String>,
+    start: usize,
+    non_terminal_ids: HashMap<String, usize>,
+    terminal_ids: HashMap<String, usize>,
+    // Rule id, line and column of every definition after the first one of a non-terminal.
+    redefinitions: Vec<(usize, usize, usize)>,
+    // Rule id and production index of the productions without a weight in rules where other
+    // productions have one.
+    missing_weights: Vec<(usize, usize)>,
+}
+
+impl Grammar {
+    // Builds a grammar from parsed rules without validating it. Alternatives of a
+    // non-terminal that is defined more than once are appended to its first definition.
+    pub fn from_rules(bnf_rules: &[BnfRule]) -> Result<Grammar, GrammarError> {
+        if bnf_rules.is_empty() {
+            return Err(GrammarError::Empty);
         }
 
-        let rule_name = parts[0].trim().to_string();
-        let expansions: Vec<Vec<String>> = parts[1]
-            .split('|')
-            .map(|expansion| expansion.trim().split_whitespace().map(String::from).collect())
-            .collect();
+        let mut grammar = Grammar {
+            rules: Vec::new(),
+            terminals: Vec::new(),
+            start: 0,
+            non_terminal_ids: HashMap::new(),
+            terminal_ids: HashMap::new(),
+            redefinitions: Vec::new(),
+            missing_weights: Vec::new(),
+        };
+
+        // Intern the defined non-terminals first so that rule ids follow the source order.
+        for bnf_rule in bnf_rules {
+            if let Some(id) = grammar.non_terminal_id(&bnf_rule.name) {
+                grammar.redefinitions.push((id, bnf_rule.line, bnf_rule.column));
+            }
+            grammar.intern_non_terminal(&bnf_rule.name, bnf_rule.line, bnf_rule.column);
+        }
+
+        let mut declared_weights = vec![Vec::new(); grammar.rules.len()];
+        for bnf_rule in bnf_rules {
+            let id = grammar.non_terminal_ids[&bnf_rule.name];
+            for bnf_production in &bnf_rule.productions {
+                declared_weights[id].push(bnf_production.weight);
+                let mut symbols = Vec::with_capacity(bnf_production.symbols.len());
+                for symbol in &bnf_production.symbols {
+                    symbols.push(match symbol {
+                        BnfSymbol::Terminal(text) => Symbol::Terminal(grammar.intern_terminal(text)),
+                        BnfSymbol::NonTerminal(name) => {
+                            Symbol::NonTerminal(grammar.intern_non_terminal(name, bnf_rule.line, bnf_rule.column))
+                        }
+                    });
+                }
+                grammar.rules[id].productions.push(Production {
+                    symbols,
+                    weight: 0.0,
+                    arity: 0,
+                    recursive: false,
+                    min_depth: None,
+                });
+            }
+        }
+
+        for (id, declared) in declared_weights.iter().enumerate() {
+            if declared.iter().any(Option::is_some) {
+                let missing = declared.iter().enumerate().filter(|(_, weight)| weight.is_none());
+                grammar.missing_weights.extend(missing.map(|(index, _)| (id, index)));
+            }
+        }
+        for (rule, declared) in grammar.rules.iter_mut().zip(declared_weights) {
+            for (production, weight) in rule.productions.iter_mut().zip(normalise_weights(&declared)) {
+                production.weight = weight;
+            }
+        }
+
+        grammar.analyse();
+        Ok(grammar)
+    }
+
+    // Loads and validates a grammar, refusing it if `validate` reports any error.
+    pub fn from_file(filename: &str) -> Result<Grammar, GrammarError> {
+        Grammar::from_file_unchecked(filename)?.checked()
+    }
 
-        grammar.insert(rule_name, expansions);
+    pub fn from_file_unchecked(filename: &str) -> Result<Grammar, GrammarError> {
+        Grammar::from_rules(&read_bnf_file(filename)?)
     }
 
-    Ok(grammar)
+    fn checked(self) -> Result<Grammar, GrammarError> {
+        let diagnostics = self.validate();
+        if !diagnostics.is_empty() {
+            Err(GrammarError::Invalid(diagnostics))
+        } else {
+            Ok(self)
+        }
+    }
+
+    fn intern_non_terminal(&mut self, name: &str, line: usize, column: usize) -> usize {
+        if let Some(&id) = self.non_terminal_ids.get(name) {
+            return id;
+        }
+        let id = self.rules.len();
+        self.rules.push(Rule {
+            name: name.to_string(),
+            productions: Vec::new(),
+            line,
+            column,
+            recursive: false,
+            component: 0,
+            min_depth: None,
+        });
+        self.non_terminal_ids.insert(name.to_string(), id);
+        id
+    }
+
+    fn intern_terminal(&mut self, text: &str) -> usize {
+        if let Some(&id) = self.terminal_ids.get(text) {
+            return id;
+        }
+        let id = self.terminals.len();
+        self.terminals.push(text.to_string());
+        self.terminal_ids.insert(text.to_string(), id);
+        id
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    pub fn rule(&self, non_terminal: usize) -> &Rule {
+        &self.rules[non_terminal]
+    }
+
+    pub fn terminals(&self) -> &[String] {
+        &self.terminals
+    }
+
+    pub fn terminal(&self, terminal: usize) -> &str {
+        &self.terminals[terminal]
+    }
+
+    pub fn non_terminal_id(&self, name: &str) -> Option<usize> {
+        self.non_terminal_ids.get(name).copied()
+    }
+
+    pub fn terminal_id(&self, text: &str) -> Option<usize> {
+        self.terminal_ids.get(text).copied()
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn set_start(&mut self, name: &str) -> Result<(), GrammarError> {
+        match self.non_terminal_id(name) {
+            Some(id) if self.rules[id].is_defined() => {
+                self.start = id;
+                Ok(())
+            }
+            _ => Err(GrammarError::UndefinedStart(name.to_string())),
+        }
+    }
+
+    // Terminal text or non-terminal name, without angle brackets.
+    pub fn symbol_name(&self, symbol: Symbol) -> &str {
+        match symbol {
+            Symbol::Terminal(id) => &self.terminals[id],
+            Symbol::NonTerminal(id) => &self.rules[id].name,
+        }
+    }
+
+    fn write_symbol(&self, f: &mut fmt::Formatter<'_>, symbol: Symbol) -> fmt::Result {
+        match symbol {
+            Symbol::NonTerminal(id) => write!(f, "<{}>", self.rules[id].name),
+            Symbol::Terminal(id) => {
+                let text = &self.terminals[id];
+                if !needs_quotes(text) {
+                    write!(f, "{}", text)
+                } else if text.contains('"') {
+                    write!(f, "'{}'", text)
+                } else {
+                    write!(f, "\"{}\"", text)
+                }
+            }
+        }
+    }
 }
 
-pub fn is_recursive(non_terminal: &str, production: &str, grammar: &Grammar) -> bool {
-    if production.contains(non_terminal) {
-        return true;
+impl FromStr for Grammar {
+    type Err = GrammarError;
+
+    fn from_str(source: &str) -> Result<Grammar, GrammarError> {
+        Grammar::from_rules(&parse_bnf(source)?)?.checked()
     }
-    for symbol in grammar.keys() {
-        if production.contains(symbol) {
-            for prod in &grammar[symbol] {
-                if is_recursive(non_terminal, prod, grammar) {
-                    return true;
+}
+
+fn normalise_weights(declared: &[Option<f64>]) -> Vec<f64> {
+    let count = declared.len() as f64;
+    let undeclared = declared.iter().filter(|weight| weight.is_none()).count();
+    let remainder = 1.0 - declared.iter().flatten().sum::<f64>();
+    let share = if undeclared > 0 { remainder.max(0.0) / undeclared as f64 } else { 0.0 };
+
+    let weights: Vec<f64> = declared.iter().map(|weight| weight.unwrap_or(share)).collect();
+    let total: f64 = weights.iter().sum();
+    if total > 0.0 {
+        weights.iter().map(|weight| weight / total).collect()
+    } else {
+        vec![1.0 / count; declared.len()]
+    }
+}
+
+// Whether a terminal would be read back differently if it were written without quotes.
+fn needs_quotes(text: &str) -> bool {
+    let chars: Vec<char> = text.chars().collect();
+    chars.is_empty()
+        || chars[0] == '#'
+        || looks_like_weight(text)
+        || (0..chars.len()).any(|i| ends_bare_terminal(&chars[i..]))
+}
+
+// Writes the grammar back out as BNF, one rule per line, starting with the start symbol.
+// Weights are written for rules whose productions are not equally likely.
+impl fmt::Display for Grammar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let order = std::iter::once(self.start).chain((0..self.rules.len()).filter(|&id| id != self.start));
+        for (i, id) in order.filter(|&id| self.rules[id].is_defined()).enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            let productions = &self.rules[id].productions;
+            let uniform = 1.0 / productions.len() as f64;
+            let weighted = productions.iter().any(|production| (production.weight - uniform).abs() > 1e-9);
+            write!(f, "<{}> ::=", self.rules[id].name)?;
+            for (j, production) in productions.iter().enumerate() {
+                if j > 0 {
+                    write!(f, " |")?;
+                }
+                for &symbol in &production.symbols {
+                    write!(f, " ")?;
+                    self.write_symbol(f, symbol)?;
+                }
+                if weighted {
+                    write!(f, " [{}]", production.weight)?;
                 }
             }
         }
+        Ok(())
     }
-    false
 }
 
-pub fn calculate_arity(production: &str, grammar: &Grammar) -> usize {
-    grammar.keys().filter(|&non_terminal| production.contains(non_terminal)).count()
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    // Used in a production but never defined.
+    UndefinedNonTerminal,
+    // Defined but not reachable from the start symbol.
+    UnreachableRule,
+    // Cannot derive a string made only of terminals.
+    NonTerminating,
+    // Defined again after its first definition.
+    DuplicateRule,
+    // The production with this index has no symbols. Write `""` for an intentionally empty one.
+    EmptyProduction(usize),
+    // The production with this index has no weight, but others of the rule have one.
+    MissingWeight(usize),
 }
 
-pub fn map_genome_to_phenotype(genome: &Genome) -> String {
-    let mut output = String::new();
-    let mut symbols = vec!["S"];
-    let mut genome_index = 0;
+// A problem found by `Grammar::validate`. `line` and `column` locate the offending rule, or
+// for an undefined non-terminal the rule that first uses it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub non_terminal: String,
+    pub line: usize,
+    pub column: usize,
+}
 
-    while let Some(top) = symbols.pop() {
-        if let Some(productions) = GRAMMAR.get(top) {
-            let gene = genome[genome_index % genome.len()];  // Cyclically use the genome
-            let production = productions[gene as usize % productions.len()];
-            for symbol in production.split_whitespace().rev() {
-                symbols.push(symbol);
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: <{}> ", self.line, self.column, self.non_terminal)?;
+        match &self.kind {
+            DiagnosticKind::UndefinedNonTerminal => write!(f, "is used but never defined"),
+            DiagnosticKind::UnreachableRule => write!(f, "is not reachable from the start symbol"),
+            DiagnosticKind::NonTerminating => write!(f, "can never derive a string of terminals"),
+            DiagnosticKind::DuplicateRule => write!(f, "is defined more than once"),
+            DiagnosticKind::EmptyProduction(index) => write!(f, "has an empty production at position {}", index + 1),
+            DiagnosticKind::MissingWeight(index) => {
+                write!(f, "has weights on some productions but none on the one at position {}", index + 1)
             }
-            genome_index += 1;  // Move to the next gene in the genome
-        } else {
-            output.push_str(top);
-            output.push(' ');
         }
     }
+}
+
+impl Grammar {
+    // Checks the grammar for undefined, unreachable, non-terminating and duplicate rules, for
+    // empty productions and for rules where only some productions have a weight. Diagnostics
+    // are sorted by their position in the source.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let diagnostic = |kind, id: usize, line, column| Diagnostic {
+            kind,
+            non_terminal: self.rules[id].name.clone(),
+            line,
+            column,
+        };
+
+        for &(id, line, column) in &self.redefinitions {
+            diagnostics.push(diagnostic(DiagnosticKind::DuplicateRule, id, line, column));
+        }
+        for &(id, index) in &self.missing_weights {
+            let rule = &self.rules[id];
+            diagnostics.push(diagnostic(DiagnosticKind::MissingWeight(index), id, rule.line, rule.column));
+        }
+
+        let reachable = self.reachable();
+        let terminating = self.terminating();
+        for (id, rule) in self.rules.iter().enumerate() {
+            if !rule.is_defined() {
+                diagnostics.push(diagnostic(DiagnosticKind::UndefinedNonTerminal, id, rule.line, rule.column));
+                continue;
+            }
+            for (index, production) in rule.productions.iter().enumerate() {
+                if production.symbols.is_empty() {
+                    diagnostics.push(diagnostic(DiagnosticKind::EmptyProduction(index), id, rule.line, rule.column));
+                }
+            }
+            if !terminating[id] {
+                diagnostics.push(diagnostic(DiagnosticKind::NonTerminating, id, rule.line, rule.column));
+            }
+            if !reachable[id] {
+                diagnostics.push(diagnostic(DiagnosticKind::UnreachableRule, id, rule.line, rule.column));
+            }
+        }
 
-    output.trim().to_string()
+        diagnostics.sort_by_key(|d| (d.line, d.column));
+        diagnostics
+    }
+
+    fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.rules.len()];
+        let mut stack = vec![self.start];
+        reachable[self.start] = true;
+
+        while let Some(id) = stack.pop() {
+            for production in &self.rules[id].productions {
+                for &symbol in &production.symbols {
+                    if let Symbol::NonTerminal(next) = symbol {
+                        if !reachable[next] {
+                            reachable[next] = true;
+                            stack.push(next);
+                        }
+                    }
+                }
+            }
+        }
+
+        reachable
+    }
+
+    // Fixpoint over "has a production whose non-terminals all terminate". Undefined
+    // non-terminals count as terminating so that they are only reported once.
+    fn terminating(&self) -> Vec<bool> {
+        let mut terminating: Vec<bool> = self.rules.iter().map(|rule| !rule.is_defined()).collect();
+        let mut changed = true;
+
+        while changed {
+            changed = false;
+            for (id, rule) in self.rules.iter().enumerate() {
+                if terminating[id] {
+                    continue;
+                }
+                let terminates = rule.productions.iter().any(|production| {
+                    production.symbols.iter().all(|&symbol| match symbol {
+                        Symbol::Terminal(_) => true,
+                        Symbol::NonTerminal(next) => terminating[next],
+                    })
+                });
+                if terminates {
+                    terminating[id] = true;
+                    changed = true;
+                }
+            }
+        }
+
+        terminating
+    }
 }
 
-pub fn genome_to_phenotype(genome: &Vec<i32>, grammar: &Grammar) -> String {
-    let mut phenotype = "S".to_string();
-    let mut gene_index = 0;
-    
-    while gene_index < genome.len() {
-        if let Some(productions) = grammar.get(&phenotype) {
-            let rule_index = genome[gene_index] as usize % productions.len();
-            phenotype = productions[rule_index].clone();
-            gene_index += 1;
-        } else {
-            break;
+impl Grammar {
+    // Fills in the recursion, arity and minimum depth annotations of every rule and production.
+    fn analyse(&mut self) {
+        let components = self.strongly_connected_components();
+
+        for (id, rule) in self.rules.iter_mut().enumerate() {
+            for production in &mut rule.productions {
+                production.arity = 0;
+                production.recursive = false;
+                for &symbol in &production.symbols {
+                    if let Symbol::NonTerminal(next) = symbol {
+                        production.arity += 1;
+                        production.recursive |= components[next] == components[id];
+                    }
+                }
+            }
+            rule.recursive = rule.productions.iter().any(|production| production.recursive);
+            rule.component = components[id];
         }
+
+        // Relax the depths until they stop shrinking; non-terminating rules stay `None`.
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for id in 0..self.rules.len() {
+                let depth = self.rules[id]
+                    .productions
+                    .iter()
+                    .filter_map(|production| self.production_depth(production))
+                    .min();
+                if depth.is_some() && (self.rules[id].min_depth.is_none() || depth < self.rules[id].min_depth) {
+                    self.rules[id].min_depth = depth;
+                    changed = true;
+                }
+            }
+        }
+
+        for id in 0..self.rules.len() {
+            for index in 0..self.rules[id].productions.len() {
+                let depth = self.production_depth(&self.rules[id].productions[index]);
+                self.rules[id].productions[index].min_depth = depth;
+            }
+        }
+    }
+
+    fn production_depth(&self, production: &Production) -> Option<usize> {
+        let mut depth = 1;
+        for &symbol in &production.symbols {
+            if let Symbol::NonTerminal(id) = symbol {
+                depth = depth.max(1 + self.rules[id].min_depth?);
+            }
+        }
+        Some(depth)
     }
-    
-    phenotype
-}
\ No newline at end of file
+
+    // Tarjan's algorithm over the "rule uses non-terminal" graph. Returns the component
+    // index of every rule; two rules share a component when each can derive the other.
+    fn strongly_connected_components(&self) -> Vec<usize> {
+        struct Tarjan<'a> {
+            grammar: &'a Grammar,
+            index: Vec<Option<usize>>,
+            low_link: Vec<usize>,
+            on_stack: Vec<bool>,
+            stack: Vec<usize>,
+            component: Vec<usize>,
+            next_index: usize,
+            next_component: usize,
+        }
+
+        fn visit(tarjan: &mut Tarjan, id: usize) {
+            tarjan.index[id] = Some(tarjan.next_index);
+            tarjan.low_link[id] = tarjan.next_index;
+            tarjan.next_index += 1;
+            tarjan.stack.push(id);
+            tarjan.on_stack[id] = true;
+
+            let grammar = tarjan.grammar;
+            for production in &grammar.rules[id].productions {
+                for &symbol in &production.symbols {
+                    if let Symbol::NonTerminal(next) = symbol {
+                        match tarjan.index[next] {
+                            None => {
+                                visit(tarjan, next);
+                                tarjan.low_link[id] = tarjan.low_link[id].min(tarjan.low_link[next]);
+                            }
+                            Some(next_index) if tarjan.on_stack[next] => {
+                                tarjan.low_link[id] = tarjan.low_link[id].min(next_index);
+                            }
+                            Some(_) => {}
+                        }
+                    }
+                }
+            }
+
+            if Some(tarjan.low_link[id]) == tarjan.index[id] {
+                while let Some(member) = tarjan.stack.pop() {
+                    tarjan.on_stack[member] = false;
+                    tarjan.component[member] = tarjan.next_component;
+                    if member == id {
+                        break;
+                    }
+                }
+                tarjan.next_component += 1;
+            }
+        }
+
+        let count = self.rules.len();
+        let mut tarjan = Tarjan {
+            grammar: self,
+            index: vec![None; count],
+            low_link: vec![0; count],
+            on_stack: vec![false; count],
+            stack: Vec::new(),
+            component: vec![0; count],
+            next_index: 0,
+            next_component: 0,
+        };
+        for id in 0..count {
+            if tarjan.index[id].is_none() {
+                visit(&mut tarjan, id);
+            }
+        }
+
+        tarjan.component
+    }
+}
+
+// Loads and validates the grammar in `filename`, like `Grammar::from_file`.
+pub fn parse_grammar(filename: &str) -> Result<Grammar, GrammarError> {
+    Grammar::from_file(filename)
+}
+
+// Parses and validates BNF source text.
+pub fn parse_grammar_str(source: &str) -> Result<Grammar, GrammarError> {
+    source.parse()
+}
+
+pub fn read_grammar_from_file(filename: &str) -> Result<Grammar, GrammarError> {
+    Grammar::from_file(filename)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grammar_path(name: &str) -> String {
+        format!("{}/grammars/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    #[test]
+    fn test_parse_bnf_symbols() {
+        let rules = parse_bnf("<e> ::= <e> + <e> | x[0] | \"|x|\" | '::='").unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].name, "e");
+        assert_eq!(
+            rules[0].productions.iter().map(|production| production.symbols.clone()).collect::<Vec<_>>(),
+            vec![
+                vec![
+                    BnfSymbol::NonTerminal("e".to_string()),
+                    BnfSymbol::Terminal("+".to_string()),
+                    BnfSymbol::NonTerminal("e".to_string()),
+                ],
+                vec![BnfSymbol::Terminal("x[0]".to_string())],
+                vec![BnfSymbol::Terminal("|x|".to_string())],
+                vec![BnfSymbol::Terminal("::=".to_string())],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_bnf_multiline_and_comments() {
+        let source = "# comment\n\n<a> ::= <b> x<=y # trailing\n      | <b>\n<b> ::= 1\n  | 2";
+        let rules = parse_bnf(source).unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!((rules[0].line, rules[0].column), (3, 1));
+        assert_eq!(rules[0].productions.len(), 2);
+        assert_eq!(rules[0].productions[0].symbols[1], BnfSymbol::Terminal("x<=y".to_string()));
+        assert_eq!(rules[1].productions.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_bnf_errors() {
+        let error = parse_bnf("<a> ::= x\n<b> ::= \"open").unwrap_err();
+        assert_eq!((error.line, error.column, error.kind), (2, 9, ParseErrorKind::UnterminatedString));
+
+        let error = parse_bnf("e ::= e + e").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 1));
+        assert_eq!(error.kind, ParseErrorKind::InvalidLeftHandSide("e".to_string()));
+
+        let error = parse_bnf("\n  x | y").unwrap_err();
+        assert_eq!((error.line, error.column, error.kind), (2, 3, ParseErrorKind::ExpectedRule));
+
+        let error = parse_bnf("<a> ::= x ::= y").unwrap_err();
+        assert_eq!((error.line, error.column, error.kind), (1, 9, ParseErrorKind::InvalidLeftHandSide("x".to_string())));
+    }
+
+    #[test]
+    fn test_parse_bnf_weights() {
+        let rules = parse_bnf("<e> ::= <e> + <e> [0.3] | x[0] [0.7]\n<c> ::= \"[1]\" [2] | '[a]' [0.5]\n<d> ::= y").unwrap();
+        let weights: Vec<Option<f64>> = rules[0].productions.iter().map(|production| production.weight).collect();
+        assert_eq!(weights, vec![Some(0.3), Some(0.7)]);
+        assert_eq!(rules[0].productions[1].symbols, vec![BnfSymbol::Terminal("x[0]".to_string())]);
+        // Terminals in brackets have to be quoted.
+        assert_eq!(rules[1].productions[0].symbols, vec![BnfSymbol::Terminal("[1]".to_string())]);
+        assert_eq!(rules[1].productions[0].weight, Some(2.0));
+        assert_eq!(rules[1].productions[1].symbols, vec![BnfSymbol::Terminal("[a]".to_string())]);
+        assert_eq!(rules[2].productions[0].weight, None);
+
+        let error = parse_bnf("<e> ::= x [-1] | y").unwrap_err();
+        assert_eq!((error.line, error.column, error.kind), (1, 11, ParseErrorKind::InvalidWeight("[-1]".to_string())));
+        let error = parse_bnf("<e> ::= a [a]").unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::InvalidWeight("[a]".to_string()));
+        let error = parse_bnf("<e> ::= a [0] b | c").unwrap_err();
+        assert_eq!((error.line, error.column, error.kind), (1, 11, ParseErrorKind::MisplacedWeight("[0]".to_string())));
+    }
+
+    #[test]
+    fn test_production_weights() {
+        let weights = |source: &str| -> Vec<f64> {
+            parse_grammar_str(source).unwrap().rule(0).productions.iter().map(|production| production.weight).collect()
+        };
+        assert_eq!(weights("<e> ::= a | b | c | d"), vec![0.25; 4]);
+        assert_eq!(weights("<e> ::= a [3] | b [1]"), vec![0.75, 0.25]);
+        assert_eq!(weights("<e> ::= a [0] | b [0]"), vec![0.5, 0.5]);
+        // A rule weights all of its productions or none.
+        let diagnostics = Grammar::from_rules(&parse_bnf("<e> ::= a [2] | b\n<e> ::= c [1]").unwrap()).unwrap().validate();
+        let kinds: Vec<DiagnosticKind> = diagnostics.into_iter().map(|diagnostic| diagnostic.kind).collect();
+        assert_eq!(kinds, vec![DiagnosticKind::MissingWeight(1), DiagnosticKind::DuplicateRule]);
+        assert!(matches!(parse_grammar_str("<e> ::= a [0.5] | b | c"), Err(GrammarError::Invalid(_))));
+
+        let printed = parse_grammar_str("<e> ::= a [0.75] | '[1]' <c> [0.25]\n<c> ::= x | y").unwrap().to_string();
+        assert_eq!(printed, "<e> ::= a [0.75] | \"[1]\" <c> [0.25]\n<c> ::= x | y");
+        assert_eq!(parse_grammar_str(&printed).unwrap().to_string(), printed);
+    }
+
+    #[test]
+    fn test_read_grammar_files() {
+        let grammar = read_grammar_from_file(&grammar_path("vlad2.bnf")).unwrap();
+        assert_eq!(grammar.rules().len(), 6);
+        assert_eq!(parse_grammar(&grammar_path("vlad2.bnf")).unwrap().to_string(), grammar.to_string());
+        assert_eq!(grammar.rule(grammar.start()).name, "Expr");
+
+        let func_expr = grammar.rule(grammar.non_terminal_id("FuncExpr").unwrap());
+        let names: Vec<&str> = func_expr.productions[0].symbols.iter().map(|&s| grammar.symbol_name(s)).collect();
+        assert_eq!(names, vec!["pow(", "Term", ",", "Term", ")"]);
+        assert!(matches!(func_expr.productions[0].symbols[1], Symbol::NonTerminal(_)));
+        assert!(matches!(func_expr.productions[0].symbols[2], Symbol::Terminal(_)));
+
+        let grammar = read_grammar_from_file(&grammar_path("vlad.bnf")).unwrap();
+        assert_eq!(grammar.rule(grammar.start()).name, "e");
+        assert_eq!(grammar.rule(grammar.non_terminal_id("c").unwrap()).productions.len(), 10);
+
+        let grammar = read_grammar_from_file(&grammar_path("vlad1.bnf")).unwrap();
+        assert_eq!(grammar.rules().len(), 6);
+        assert_eq!(Grammar::from_file_unchecked(&grammar_path("vlad1.bnf")).unwrap().to_string(), grammar.to_string());
+    }
+
+    #[test]
+    fn test_validate() {
+        let source = "<s> ::= <a> | <b> |\n<a> ::= <a> x\n<b> ::= <c> | y\n<d> ::= z\n<b> ::= \"\"";
+        let grammar = Grammar::from_rules(&parse_bnf(source).unwrap()).unwrap();
+        let diagnostics = grammar.validate();
+        let found: Vec<(DiagnosticKind, &str, usize)> =
+            diagnostics.iter().map(|d| (d.kind.clone(), d.non_terminal.as_str(), d.line)).collect();
+        assert_eq!(
+            found,
+            vec![
+                (DiagnosticKind::EmptyProduction(2), "s", 1),
+                (DiagnosticKind::NonTerminating, "a", 2),
+                (DiagnosticKind::UndefinedNonTerminal, "c", 3),
+                (DiagnosticKind::UnreachableRule, "d", 4),
+                (DiagnosticKind::DuplicateRule, "b", 5),
+            ]
+        );
+
+        assert!(matches!(source.parse::<Grammar>(), Err(GrammarError::Invalid(_))));
+
+        // An unreachable rule is enough to refuse a grammar.
+        match parse_grammar_str("<e> ::= <e> + <e> | x\n<c> ::= 0 | 1") {
+            Err(GrammarError::Invalid(diagnostics)) => {
+                let found: Vec<(DiagnosticKind, &str)> = diagnostics.iter().map(|d| (d.kind.clone(), d.non_terminal.as_str())).collect();
+                assert_eq!(found, vec![(DiagnosticKind::UnreachableRule, "c")]);
+                assert_eq!(diagnostics[0].to_string(), "line 2, column 1: <c> is not reachable from the start symbol");
+            }
+            other => panic!("expected an unreachable rule, got {:?}", other),
+        }
+
+        // `<e>` can only ever expand to more `<e>`s.
+        match parse_grammar_str("<e> ::= <f> + <e> | ( <e> )\n<f> ::= x | <e>") {
+            Err(GrammarError::Invalid(diagnostics)) => {
+                assert!(diagnostics.iter().any(|d| d.kind == DiagnosticKind::NonTerminating && d.non_terminal == "e"));
+            }
+            other => panic!("expected a non-terminating rule, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_grammar_symbols() {
+        let source = "<a> ::= <b> x | \"<b>\" <c>\n<b> ::= x | <a>\n<a> ::= y";
+        let mut grammar = Grammar::from_rules(&parse_bnf(source).unwrap()).unwrap();
+        assert_eq!(grammar.rules().len(), 3);
+        assert_eq!(grammar.start(), 0);
+
+        // `<c>` is referenced but never defined, and the quoted "<b>" is a terminal.
+        let c = grammar.non_terminal_id("c").unwrap();
+        assert!(!grammar.rule(c).is_defined());
+        let a = grammar.rule(0);
+        assert_eq!(a.productions.len(), 3);
+        assert_eq!(a.productions[1].symbols[0], Symbol::Terminal(grammar.terminal_id("<b>").unwrap()));
+        assert_eq!(grammar.symbol_name(a.productions[2].symbols[0]), "y");
+        assert_eq!(a.productions[0].symbols[1], grammar.rule(1).productions[0].symbols[0]);
+
+        grammar.set_start("b").unwrap();
+        assert_eq!(grammar.start(), 1);
+        assert!(matches!(grammar.set_start("c"), Err(GrammarError::UndefinedStart(_))));
+        assert!(matches!(parse_grammar_str("# nothing here"), Err(GrammarError::Empty)));
+    }
+
+    #[test]
+    fn test_grammar_display_round_trip() {
+        let source = "<e> ::= <e> \"|\" <e> | '\"' | x[0] | \"a b\" | \"<c>\" <c>\n<c> ::= <=";
+        let grammar = parse_grammar_str(source).unwrap();
+        let printed = grammar.to_string();
+        assert_eq!(printed, "<e> ::= <e> \"|\" <e> | '\"' | x[0] | \"a b\" | \"<c>\" <c>\n<c> ::= <=");
+        assert_eq!(parse_grammar_str(&printed).unwrap().to_string(), printed);
+    }
+
+    #[test]
+    fn test_recursion_and_arity() {
+        // `<E>` appearing in `<Expr>` must not make the first production look recursive.
+        let grammar = parse_grammar_str("<Expr> ::= <E> + <E> | ( <Expr> )\n<E> ::= x | y").unwrap();
+        let expr = grammar.rule(0);
+        assert!(expr.recursive);
+        assert_eq!((expr.productions[0].recursive, expr.productions[0].arity), (false, 2));
+        assert_eq!((expr.productions[1].recursive, expr.productions[1].arity), (true, 1));
+        assert!(!grammar.rule(1).recursive);
+
+        let grammar = parse_grammar_str("<a> ::= <b> | x\n<b> ::= <a> z | <c>\n<c> ::= y").unwrap();
+        let recursive: Vec<Vec<bool>> = grammar
+            .rules()
+            .iter()
+            .map(|rule| rule.productions.iter().map(|production| production.recursive).collect())
+            .collect();
+        assert_eq!(recursive, vec![vec![true, false], vec![true, false], vec![false]]);
+    }
+
+    #[test]
+    fn test_min_depth() {
+        let grammar = read_grammar_from_file(&grammar_path("vlad2.bnf")).unwrap();
+        let depth = |name: &str| grammar.rule(grammar.non_terminal_id(name).unwrap()).min_depth;
+        assert_eq!(depth("Digit"), Some(1));
+        assert_eq!(depth("Const"), Some(2));
+        assert_eq!(depth("Term"), Some(1));
+        assert_eq!(depth("FuncExpr"), Some(2));
+        assert_eq!(depth("Expr"), Some(3));
+        assert_eq!(depth("ArithExpr"), Some(4));
+
+        let term = grammar.rule(grammar.non_terminal_id("Term").unwrap());
+        assert_eq!(term.productions[0].min_depth, Some(1));
+        assert_eq!(term.productions[5].min_depth, Some(3));
+
+        let grammar = Grammar::from_rules(&parse_bnf("<e> ::= <e> + <e> | ( <e> )").unwrap()).unwrap();
+        assert_eq!(grammar.rule(grammar.start()).min_depth, None);
+    }
+}
@@ -0,0 +1,199 @@
+use std::cmp;
+
+use crate::derivation::DerivationTree;
+use crate::fitness::{compare_optional, Direction, Fitness};
+use crate::genome::Genome;
+use crate::grammar::Grammar;
+use crate::mapper::{Mapper, MappingResult};
+
+pub type Population<F = f64> = Vec<Individual<F>>;
+
+// Hands out the ids of a run's individuals. Each island has its own generator and the islands'
+// ids are interleaved, so ids are unique within the run and do not depend on the order in
+// which islands are processed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdGenerator {
+    next: usize,
+    step: usize,
+}
+
+impl IdGenerator {
+    // Generator of island `index` out of `count`. Ids start at 1.
+    pub fn new(index: usize, count: usize) -> Self {
+        IdGenerator { next: index + 1, step: count.max(1) }
+    }
+
+    pub fn next_id(&mut self) -> usize {
+        let id = self.next;
+        self.next += self.step;
+        id
+    }
+}
+
+// A genome together with what has been worked out about it. The mapping and the fitness are
+// cached, so an individual copied unchanged into the next generation is not mapped or
+// evaluated again; variation operators create new individuals rather than editing genomes
+// in place.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Individual<F = f64> {
+    // Unique among the individuals of a run, from an `IdGenerator`; 0 for individuals made
+    // outside one. A survivor keeps its id, while a copy of an individual gets a new one.
+    pub id: usize,
+    pub genome: Genome,
+    // `None` until mapped.
+    pub phenotype: Option<String>,
+    // Only kept when the mapper builds trees.
+    pub tree: Option<DerivationTree>,
+    pub used_codons: usize,
+    // Depth of the deepest expanded non-terminal, the start symbol being at depth 1.
+    pub depth: usize,
+    pub valid: bool,
+    // `None` until evaluated.
+    pub fitness: Option<F>,
+    // Error on each training case, lower being better, when the selection method needs them
+    // and the problem provides them.
+    pub case_errors: Option<Vec<f64>>,
+    // Generations survived unchanged.
+    pub age: usize,
+    // Ids of the individuals this one was bred from; empty for the initial population.
+    pub parents: Vec<usize>,
+}
+
+impl<F: Fitness> Individual<F> {
+    pub fn new(genome: Genome) -> Self {
+        Individual {
+            id: 0,
+            genome,
+            phenotype: None,
+            tree: None,
+            used_codons: 0,
+            depth: 0,
+            valid: false,
+            fitness: None,
+            case_errors: None,
+            age: 0,
+            parents: Vec::new(),
+        }
+    }
+
+    pub fn offspring(genome: Genome, parents: &[&Individual<F>], ids: &mut IdGenerator) -> Self {
+        Individual { id: ids.next_id(), parents: parents.iter().map(|parent| parent.id).collect(), ..Individual::new(genome) }
+    }
+
+    // The same individual one generation older, keeping its mapping and fitness. A population
+    // should hold at most one survivor of an individual.
+    pub fn survivor(&self) -> Self {
+        Individual { age: self.age + 1, ..self.clone() }
+    }
+
+    // An unchanged copy bred from this individual, e.g. a parent that was neither recombined
+    // nor mutated or a migrant. It keeps the mapping and fitness and ages like a survivor, but
+    // is a new individual with this one as its parent.
+    pub fn copy(&self, ids: &mut IdGenerator) -> Self {
+        Individual { id: ids.next_id(), parents: vec![self.id], ..self.survivor() }
+    }
+
+    pub fn is_mapped(&self) -> bool {
+        self.phenotype.is_some()
+    }
+
+    pub fn is_evaluated(&self) -> bool {
+        self.fitness.is_some()
+    }
+
+    // Maps the genome unless it has been mapped already.
+    pub fn map(&mut self, grammar: &Grammar, mapper: &Mapper) {
+        self.map_with(|genome| mapper.map(grammar, genome));
+    }
+
+    // Maps the genome with `map` unless it has been mapped already, for representations other
+    // than classic GE.
+    pub fn map_with(&mut self, map: impl FnOnce(&[usize]) -> MappingResult) {
+        if self.is_mapped() {
+            return;
+        }
+        let result = map(&self.genome);
+        self.valid = result.is_valid();
+        self.used_codons = result.used_codons;
+        self.depth = result.max_depth;
+        self.tree = result.tree;
+        self.phenotype = Some(result.phenotype);
+    }
+
+    // Fitness, computed with `fitness` only if the individual has not been evaluated yet.
+    pub fn evaluate(&mut self, fitness: &dyn Fn(&Individual<F>) -> F) -> &F {
+        if self.fitness.is_none() {
+            self.fitness = Some(fitness(self));
+        }
+        self.fitness.as_ref().unwrap()
+    }
+
+    // `cmp::Ordering::Less` when this individual is fitter than `other`. Unevaluated
+    // individuals are the worst.
+    pub fn compare(&self, other: &Individual<F>, direction: Direction) -> cmp::Ordering {
+        compare_optional(self.fitness.as_ref(), other.fitness.as_ref(), direction)
+    }
+}
+
+// The fittest individual, or `None` for an empty population.
+pub fn best<F: Fitness>(population: &[Individual<F>], direction: Direction) -> Option<&Individual<F>> {
+    population.iter().min_by(|a, b| a.compare(b, direction))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::parse_grammar_str;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_mapping_and_evaluation_are_cached() {
+        let grammar = parse_grammar_str("<e> ::= <e> + <e> | x | y").unwrap();
+        let mut individual: Individual = Individual::new(vec![0, 1, 2]);
+        assert!(!individual.is_mapped() && !individual.is_evaluated());
+
+        individual.map(&grammar, &Mapper::default());
+        assert_eq!(individual.phenotype.as_deref(), Some("x + y"));
+        assert!(individual.valid);
+        assert_eq!(individual.used_codons, 3);
+
+        let calls = Cell::new(0);
+        let fitness = |individual: &Individual| {
+            calls.set(calls.get() + 1);
+            individual.phenotype.as_ref().unwrap().len() as f64
+        };
+        assert_eq!(*individual.evaluate(&fitness), 5.0);
+        let mut survivor = individual.survivor();
+        assert_eq!(*survivor.evaluate(&fitness), 5.0);
+        assert_eq!(calls.get(), 1);
+        assert_eq!((survivor.id, survivor.age), (individual.id, 1));
+    }
+
+    #[test]
+    fn test_offspring() {
+        let mut ids = IdGenerator::new(1, 3);
+        let a: Individual<usize> = Individual::offspring(vec![0], &[], &mut ids);
+        let b = Individual::offspring(vec![1], &[], &mut ids);
+        assert_eq!((a.id, b.id), (2, 5));
+        let child = Individual::offspring(vec![0, 1], &[&a, &b], &mut ids);
+        assert_eq!(child.parents, vec![a.id, b.id]);
+        assert_eq!(child.id, 8);
+        assert_eq!((child.age, child.fitness), (0, None));
+
+        let b = Individual { fitness: Some(3), ..b };
+        let copy = b.copy(&mut ids);
+        assert_eq!((copy.id, copy.parents, copy.age, copy.fitness), (11, vec![b.id], 1, Some(3)));
+        assert_eq!(Individual::<usize>::new(vec![2]).id, 0);
+    }
+
+    #[test]
+    fn test_best() {
+        let population: Population<(f64, usize)> = [Some((0.5, 9)), None, Some((0.5, 3)), Some((0.7, 1))]
+            .into_iter()
+            .map(|fitness| Individual { fitness, ..Individual::new(Vec::new()) })
+            .collect();
+        assert_eq!(best(&population, Direction::Minimize).unwrap().fitness, Some((0.5, 3)));
+        assert_eq!(best(&population, Direction::Maximize).unwrap().fitness, Some((0.7, 1)));
+        assert!(best::<f64>(&[], Direction::Minimize).is_none());
+    }
+}
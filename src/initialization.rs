@@ -0,0 +1,404 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+use rand::Rng;
+
+use crate::derivation::{Choice, DerivationTree};
+use crate::genome::{Genome, MAX_GENE_VALUE};
+use crate::grammar::{Grammar, Production, Symbol};
+use crate::mapper::MappingStrategy;
+use crate::pge::ProductionWeights;
+
+// How many times a slot of the population is regenerated before a duplicate phenotype is
+// accepted anyway, for grammars with fewer distinct derivations than the population size.
+const MAX_DUPLICATE_ATTEMPTS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitMethod {
+    // Any production that fits within the depth limit.
+    Grow,
+    // Recursive productions while they fit, so every branch reaches the depth limit where the
+    // grammar allows it.
+    Full,
+    // Position-independent grow: non-terminals are expanded in random order, and recursive
+    // productions are forced until some branch reaches the depth limit, so only one branch
+    // has to be deep and the rest are grown.
+    PiGrow,
+}
+
+// How an initialiser spreads the population over tree shapes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Initialization {
+    // Alternating `Grow` and `Full` at each depth of the ramp.
+    #[default]
+    RampedHalfAndHalf,
+    // `PiGrow` at each depth of the ramp.
+    PiGrow,
+}
+
+// An individual produced by derivation-space initialisation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Derivation {
+    pub genome: Genome,
+    // Number of codons that encode the derivation; the rest of the genome is a random tail.
+    pub used_codons: usize,
+    pub phenotype: String,
+    pub depth: usize,
+}
+
+// Number of individuals at each derivation depth.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DepthDistribution {
+    pub counts: BTreeMap<usize, usize>,
+}
+
+impl DepthDistribution {
+    pub fn new(derivations: &[Derivation]) -> Self {
+        DepthDistribution::from_depths(derivations.iter().map(|derivation| derivation.depth))
+    }
+
+    pub fn from_depths(depths: impl IntoIterator<Item = usize>) -> Self {
+        let mut counts = BTreeMap::new();
+        for depth in depths {
+            *counts.entry(depth).or_insert(0) += 1;
+        }
+        DepthDistribution { counts }
+    }
+}
+
+// One line per depth, e.g. `depth 4: 12`.
+impl fmt::Display for DepthDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (depth, count)) in self.counts.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "depth {}: {}", depth, count)?;
+        }
+        Ok(())
+    }
+}
+
+// How genomes encode derivations, so that the mapper reads back the derived phenotype: the
+// order in which `Mapper::strategy` expands non-terminals, and for probabilistic GE the
+// weights `Mapper::map_probabilistic` chooses productions by.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Encoding<'a> {
+    pub strategy: MappingStrategy,
+    pub weights: Option<&'a ProductionWeights>,
+}
+
+impl Encoding<'_> {
+    // Whether some codon chooses `production` of `rule`. Under production weights, productions
+    // whose weight is too small for any codon cannot be encoded.
+    pub fn can_choose(&self, rule: usize, production: usize) -> bool {
+        self.weights.is_none_or(|weights| !weights.codons(rule, production).is_empty())
+    }
+
+    // A random codon that chooses `production` of `rule`. A production that cannot be encoded
+    // gets the codon at its place on the roulette wheel, which chooses a neighbour.
+    pub fn encode_choice(&self, grammar: &Grammar, rule: usize, production: usize, rng: &mut impl Rng) -> usize {
+        match self.weights {
+            None => encode_choice(production, grammar.rule(rule).productions.len(), rng),
+            Some(weights) => {
+                let codons = weights.codons(rule, production);
+                if codons.is_empty() { codons.start.min(MAX_GENE_VALUE) } else { rng.gen_range(codons) }
+            }
+        }
+    }
+
+    // Codons from which a mapper with this encoding's strategy derives the complete `tree`,
+    // recording the position of each choice's codon in the tree. πGE genomes expand the
+    // non-terminals in random order.
+    pub fn encode(&self, grammar: &Grammar, tree: &mut DerivationTree, rng: &mut impl Rng) -> Genome {
+        let mut genome = Genome::new();
+        // Unexpanded non-terminal nodes, kept as `Mapper` keeps them.
+        let mut frontier = vec![0];
+        while !frontier.is_empty() {
+            let position = match self.strategy {
+                MappingStrategy::DepthFirst | MappingStrategy::BreadthFirst => 0,
+                MappingStrategy::RightMost => frontier.len() - 1,
+                MappingStrategy::PiGE => {
+                    let position = rng.gen_range(0..frontier.len());
+                    genome.push(encode_choice(position, frontier.len(), rng));
+                    position
+                }
+            };
+            let node = frontier[position];
+            let (Symbol::NonTerminal(rule), Some(choice)) = (tree.nodes[node].symbol, tree.nodes[node].choice.as_mut()) else {
+                unreachable!("the tree is complete")
+            };
+            choice.codon = genome.len();
+            genome.push(self.encode_choice(grammar, rule, choice.production, rng));
+
+            let children = tree.nodes[node].children.iter().copied();
+            let open: Vec<usize> = children.filter(|&child| matches!(tree.nodes[child].symbol, Symbol::NonTerminal(_))).collect();
+            if self.strategy == MappingStrategy::BreadthFirst {
+                frontier.remove(position);
+                frontier.extend(open);
+            } else {
+                frontier.splice(position..=position, open);
+            }
+        }
+        genome
+    }
+}
+
+// Sensible initialisation over derivation trees. Depth limits are ramped from `min_depth` to
+// `max_depth` and trees are derived at each depth with the chosen `method`. The production
+// choices are encoded as the codons a `Mapper` with the given `Encoding` reads back. Depths
+// count the start symbol as 1, like `MappingResult::max_depth`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SensibleInitializer {
+    pub method: Initialization,
+    // Raised to the start symbol's `min_depth` if shallower.
+    pub min_depth: usize,
+    pub max_depth: usize,
+    // Length of the random tail appended to each genome, as a fraction of the used codons.
+    pub tail_ratio: f64,
+    pub remove_duplicates: bool,
+}
+
+impl Default for SensibleInitializer {
+    fn default() -> Self {
+        SensibleInitializer {
+            method: Initialization::RampedHalfAndHalf,
+            min_depth: 1,
+            max_depth: 10,
+            tail_ratio: 0.5,
+            remove_duplicates: true,
+        }
+    }
+}
+
+impl SensibleInitializer {
+    pub fn initialize_population(&self, grammar: &Grammar, size: usize, encoding: Encoding, rng: &mut impl Rng) -> Vec<Genome> {
+        self.initialize(grammar, size, encoding, rng).into_iter().map(|derivation| derivation.genome).collect()
+    }
+
+    // The initial population with the derivation behind each genome, for reporting e.g. a
+    // `DepthDistribution`.
+    pub fn initialize(&self, grammar: &Grammar, size: usize, encoding: Encoding, rng: &mut impl Rng) -> Vec<Derivation> {
+        let start_depth = grammar.rule(grammar.start()).min_depth.expect("the start symbol must terminate");
+        let min_depth = self.min_depth.max(start_depth);
+        let max_depth = self.max_depth.max(min_depth);
+        let depths = max_depth - min_depth + 1;
+
+        let mut population = Vec::with_capacity(size);
+        let mut phenotypes = HashSet::new();
+        for i in 0..size {
+            let depth = min_depth + (i / 2) % depths;
+            let method = match self.method {
+                Initialization::RampedHalfAndHalf if i % 2 == 0 => InitMethod::Grow,
+                Initialization::RampedHalfAndHalf => InitMethod::Full,
+                Initialization::PiGrow => InitMethod::PiGrow,
+            };
+            let mut derivation = self.derive(grammar, method, depth, encoding, rng);
+            for _ in 1..MAX_DUPLICATE_ATTEMPTS {
+                if !self.remove_duplicates || !phenotypes.contains(&derivation.phenotype) {
+                    break;
+                }
+                derivation = self.derive(grammar, method, depth, encoding, rng);
+            }
+            phenotypes.insert(derivation.phenotype.clone());
+            population.push(derivation);
+        }
+        population
+    }
+
+    // Derives a random tree of depth at most `max_depth` (which must be at least the start
+    // symbol's `min_depth`) and encodes it.
+    pub fn derive(
+        &self,
+        grammar: &Grammar,
+        method: InitMethod,
+        max_depth: usize,
+        encoding: Encoding,
+        rng: &mut impl Rng,
+    ) -> Derivation {
+        let mut tree = grow_tree(grammar, method, max_depth, encoding, rng);
+        let genome = encoding.encode(grammar, &mut tree, rng);
+        self.finish(genome, tree.phenotype(grammar), tree.depth(), rng)
+    }
+
+    fn finish(&self, mut genome: Genome, phenotype: String, depth: usize, rng: &mut impl Rng) -> Derivation {
+        let used_codons = genome.len();
+        let tail = (used_codons as f64 * self.tail_ratio).round() as usize;
+        genome.extend((0..tail).map(|_| rng.gen_range(0..=MAX_GENE_VALUE)));
+        Derivation { genome, used_codons, phenotype, depth }
+    }
+}
+
+// Grows a derivation tree within `max_depth` out of productions `encoding` can encode where
+// possible. Grow and full expand the leftmost open non-terminal each step, πgrow a random one.
+fn grow_tree(grammar: &Grammar, method: InitMethod, max_depth: usize, encoding: Encoding, rng: &mut impl Rng) -> DerivationTree {
+    let mut tree = DerivationTree::new(Symbol::NonTerminal(grammar.start()));
+    // Open non-terminal nodes, the leftmost last.
+    let mut open = vec![0];
+    let mut depth = 0;
+
+    while !open.is_empty() {
+        let node = match method {
+            InitMethod::PiGrow => open.swap_remove(rng.gen_range(0..open.len())),
+            InitMethod::Grow | InitMethod::Full => open.pop().unwrap(),
+        };
+        let Symbol::NonTerminal(id) = tree.nodes[node].symbol else {
+            unreachable!("only non-terminals are open")
+        };
+        let node_depth = tree.nodes[node].depth;
+        let recurse = match method {
+            InitMethod::Grow => false,
+            InitMethod::Full => true,
+            InitMethod::PiGrow => depth < max_depth,
+        };
+        let productions = &grammar.rule(id).productions;
+        let encodable = |production| encoding.can_choose(id, production);
+        let production = choose_production(productions, node_depth, max_depth, recurse, encodable, rng);
+        depth = depth.max(node_depth);
+
+        let children = tree.expand(node, Choice { production, codon: 0 }, &productions[production].symbols);
+        open.extend(children.rev().filter(|&child| matches!(tree.nodes[child].symbol, Symbol::NonTerminal(_))));
+    }
+    tree
+}
+
+// A random production of a non-terminal at `node_depth` whose shallowest derivation fits within
+// `max_depth`, restricted to `encodable` productions if any fits, and then to recursive
+// productions when `recurse` is set and there are any.
+fn choose_production(
+    productions: &[Production],
+    node_depth: usize,
+    max_depth: usize,
+    recurse: bool,
+    encodable: impl Fn(usize) -> bool,
+    rng: &mut impl Rng,
+) -> usize {
+    let fits = |production: &Production| {
+        production.min_depth.is_some_and(|min_depth| node_depth + min_depth - 1 <= max_depth)
+    };
+    let mut candidates: Vec<usize> = (0..productions.len()).filter(|&p| fits(&productions[p])).collect();
+    if candidates.iter().any(|&p| encodable(p)) {
+        candidates.retain(|&p| encodable(p));
+    }
+    if recurse && candidates.iter().any(|&p| productions[p].recursive) {
+        candidates.retain(|&p| productions[p].recursive);
+    }
+    candidates[rng.gen_range(0..candidates.len())]
+}
+
+// A random codon that chooses `production` out of `count` under `codon % count`. No codon in the
+// gene range chooses a production past `MAX_GENE_VALUE`, so it gets the codon `production`.
+pub fn encode_choice(production: usize, count: usize, rng: &mut impl Rng) -> usize {
+    production + count * rng.gen_range(0..=MAX_GENE_VALUE.saturating_sub(production) / count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::parse_grammar_str;
+    use crate::mapper::Mapper;
+    use crate::random;
+
+    fn arithmetic_grammar() -> Grammar {
+        parse_grammar_str("<e> ::= <e> <op> <e> | ( <e> ) | <v>\n<op> ::= + | *\n<v> ::= x | y").unwrap()
+    }
+
+    #[test]
+    fn test_derive_round_trips_through_mapper() {
+        let grammar = arithmetic_grammar();
+        let initializer = SensibleInitializer::default();
+        let mut rng = random::seeded(11);
+        let strategies = [MappingStrategy::DepthFirst, MappingStrategy::BreadthFirst, MappingStrategy::RightMost, MappingStrategy::PiGE];
+        for strategy in strategies {
+            for method in [InitMethod::Grow, InitMethod::Full, InitMethod::PiGrow] {
+                for max_depth in 2..8 {
+                    let derivation = initializer.derive(&grammar, method, max_depth, Encoding { strategy, weights: None }, &mut rng);
+                    assert!(derivation.depth <= max_depth);
+                    assert_eq!(derivation.genome.len(), derivation.used_codons + (derivation.used_codons as f64 * 0.5).round() as usize);
+
+                    let result = Mapper { max_wraps: 0, strategy, ..Mapper::default() }.map(&grammar, &derivation.genome);
+                    assert!(result.is_valid());
+                    assert_eq!(result.phenotype, derivation.phenotype, "{:?}", strategy);
+                    assert_eq!((result.used_codons, result.max_depth), (derivation.used_codons, derivation.depth));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_derive_round_trips_through_probabilistic_mapper() {
+        // No codon chooses `( <e> )`, so the initialiser must not use it.
+        let source = "<e> ::= <e> <op> <e> [0.301] | ( <e> ) [0.001] | <v> [0.698]\n<op> ::= + | *\n<v> ::= x | y";
+        let grammar = parse_grammar_str(source).unwrap();
+        let weights = ProductionWeights::new(&grammar);
+        let mut rng = random::seeded(14);
+        for strategy in [MappingStrategy::DepthFirst, MappingStrategy::BreadthFirst] {
+            let encoding = Encoding { strategy, weights: Some(&weights) };
+            for derivation in SensibleInitializer::default().initialize(&grammar, 50, encoding, &mut rng) {
+                let mapper = Mapper { max_wraps: 0, strategy, ..Mapper::default() };
+                let result = mapper.map_probabilistic(&grammar, &derivation.genome, &weights);
+                assert!(result.is_valid());
+                assert_eq!(result.phenotype, derivation.phenotype);
+                assert!(!derivation.phenotype.contains('('));
+            }
+        }
+    }
+
+    #[test]
+    fn test_rule_with_more_productions_than_codon_values() {
+        let alternatives: Vec<String> = (0..300).map(|i| format!("c{}", i)).collect();
+        let grammar = parse_grammar_str(&format!("<e> ::= {}", alternatives.join(" | "))).unwrap();
+        let mut rng = random::seeded(15);
+        for _ in 0..50 {
+            let derivation = SensibleInitializer::default().derive(&grammar, InitMethod::Grow, 1, Encoding::default(), &mut rng);
+            assert_eq!(Mapper::default().map(&grammar, &derivation.genome).phenotype, derivation.phenotype);
+        }
+        assert_eq!(encode_choice(299, 300, &mut rng), 299);
+        assert_eq!(encode_choice(255, 300, &mut rng), 255);
+    }
+
+    #[test]
+    fn test_full_and_pi_grow_reach_max_depth() {
+        let grammar = arithmetic_grammar();
+        let mut rng = random::seeded(11);
+        for _ in 0..20 {
+            for method in [InitMethod::Full, InitMethod::PiGrow] {
+                let derivation = SensibleInitializer::default().derive(&grammar, method, 6, Encoding::default(), &mut rng);
+                assert_eq!(derivation.depth, 6);
+            }
+        }
+    }
+
+    #[test]
+    fn test_initialize_population() {
+        let grammar = arithmetic_grammar();
+        let mut rng = random::seeded(12);
+        let initializer = SensibleInitializer { min_depth: 4, max_depth: 8, ..SensibleInitializer::default() };
+        let population = initializer.initialize_population(&grammar, 50, Encoding::default(), &mut rng);
+        assert_eq!(population.len(), 50);
+
+        let mapper = Mapper::default();
+        let phenotypes: HashSet<String> = population.iter().map(|genome| mapper.map(&grammar, genome).phenotype).collect();
+        assert_eq!(phenotypes.len(), 50);
+        assert!(population.iter().all(|genome| mapper.map(&grammar, genome).max_depth <= 8));
+
+        // Only two distinct derivations fit within depth 2, so duplicates are kept.
+        let initializer = SensibleInitializer { max_depth: 2, ..SensibleInitializer::default() };
+        assert_eq!(initializer.initialize_population(&grammar, 10, Encoding::default(), &mut rng).len(), 10);
+    }
+
+    #[test]
+    fn test_pi_grow_depth_distribution() {
+        let grammar = arithmetic_grammar();
+        let initializer = SensibleInitializer {
+            method: Initialization::PiGrow,
+            min_depth: 3,
+            max_depth: 5,
+            ..SensibleInitializer::default()
+        };
+        let derivations = initializer.initialize(&grammar, 30, Encoding::default(), &mut random::seeded(13));
+        let distribution = DepthDistribution::new(&derivations);
+        assert_eq!(distribution.counts, BTreeMap::from([(3, 10), (4, 10), (5, 10)]));
+        assert_eq!(distribution.to_string(), "depth 3: 10\ndepth 4: 10\ndepth 5: 10");
+    }
+}
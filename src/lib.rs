@@ -1,4 +1,15 @@
+pub mod bloat;
+pub mod derivation;
+pub mod engine;
+pub mod fitness;
 pub mod genome;
 pub mod grammar;
+pub mod individual;
+pub mod initialization;
+pub mod mapper;
+pub mod pge;
+pub mod random;
+pub mod reverse_mapping;
+pub mod sge;
 pub mod evolutionary_ops;
 pub mod population_mgmt;
\ No newline at end of file
@@ -1,5 +1,3 @@
-use gers_dev::grammar;
-
 fn main() {
     println!("GERs-dev");
 }
\ No newline at end of file
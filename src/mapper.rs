@@ -0,0 +1,429 @@
+use crate::derivation::{Choice, DerivationTree};
+use crate::grammar::{Grammar, Symbol};
+use crate::pge::ProductionWeights;
+
+// Maximum number of times the genome is reread from the start when it runs out of codons.
+pub const DEFAULT_MAX_WRAPS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidReason {
+    // The genome ran out of codons after `max_wraps` wraps (or was empty).
+    WrapLimit,
+    // A non-terminal would have been expanded deeper than `max_depth`.
+    DepthLimit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MappingStatus {
+    Valid,
+    Invalid(InvalidReason),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MappingResult {
+    // Terminals separated by single spaces. When the mapping is invalid this is the partial
+    // derivation, with the non-terminals that were never expanded written as `<name>`.
+    pub phenotype: String,
+    // Codons read, counting rereads after a wrap. `used_codons.min(genome.len())` is the
+    // length of the genome prefix that influenced the phenotype.
+    pub used_codons: usize,
+    pub wraps: usize,
+    // Depth of the deepest expanded non-terminal; the start symbol is at depth 1.
+    pub max_depth: usize,
+    pub status: MappingStatus,
+    // Only built when `Mapper::build_tree` is set.
+    pub tree: Option<DerivationTree>,
+}
+
+impl MappingResult {
+    pub fn is_valid(&self) -> bool {
+        self.status == MappingStatus::Valid
+    }
+}
+
+// Order in which the non-terminals of the sentential form are expanded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MappingStrategy {
+    // Always expand the leftmost non-terminal (standard GE).
+    #[default]
+    DepthFirst,
+    // Expand non-terminals level by level, left to right.
+    BreadthFirst,
+    // Always expand the rightmost non-terminal.
+    RightMost,
+    // Position-independent GE: codons are read in pairs, the first choosing which of the
+    // unexpanded non-terminals (in left-to-right order) to expand and the second its production.
+    PiGE,
+}
+
+// Genome-to-phenotype mapping. Each codon chooses a production of the non-terminal being
+// expanded by `codon % number_of_productions`, or against the production weights when
+// mapping probabilistically. The grammar is expected to have passed `Grammar::validate`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mapper {
+    pub max_wraps: usize,
+    // Derivation depth limit, or `None` for no limit.
+    pub max_depth: Option<usize>,
+    pub build_tree: bool,
+    pub strategy: MappingStrategy,
+}
+
+impl Default for Mapper {
+    fn default() -> Self {
+        Mapper {
+            max_wraps: DEFAULT_MAX_WRAPS,
+            max_depth: None,
+            build_tree: false,
+            strategy: MappingStrategy::DepthFirst,
+        }
+    }
+}
+
+// Reads the genome from the start, wrapping round at most `max_wraps` times. Yields the
+// position and value of each codon.
+struct Codons<'a> {
+    genome: &'a [usize],
+    max_wraps: usize,
+    used: usize,
+    wraps: usize,
+}
+
+impl Iterator for Codons<'_> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        if self.genome.is_empty() || self.used / self.genome.len() > self.max_wraps {
+            return None;
+        }
+        let index = self.used % self.genome.len();
+        if index == 0 && self.used > 0 {
+            self.wraps += 1;
+        }
+        self.used += 1;
+        Some((index, self.genome[index]))
+    }
+}
+
+// Terminals written so far followed by the symbols still on a leftmost-derivation stack
+// (top of the stack last), with unexpanded non-terminals written as `<name>`.
+pub(crate) fn partial_phenotype(
+    grammar: &Grammar,
+    output: &[&str],
+    stack: impl DoubleEndedIterator<Item = Symbol>,
+) -> String {
+    let mut phenotype = output.join(" ");
+    for symbol in stack.rev() {
+        if !phenotype.is_empty() {
+            phenotype.push(' ');
+        }
+        match symbol {
+            Symbol::Terminal(id) => phenotype.push_str(grammar.terminal(id)),
+            Symbol::NonTerminal(id) => phenotype.push_str(&format!("<{}>", grammar.rule(id).name)),
+        }
+    }
+    phenotype
+}
+
+// Production of `rule` chosen by a codon.
+fn choose(grammar: &Grammar, weights: Option<&ProductionWeights>, rule: usize, codon: usize) -> usize {
+    match weights {
+        Some(weights) => weights.choose(rule, codon),
+        None => codon % grammar.rule(rule).productions.len(),
+    }
+}
+
+impl Mapper {
+    pub fn map(&self, grammar: &Grammar, genome: &[usize]) -> MappingResult {
+        self.map_with(grammar, genome, None)
+    }
+
+    // Probabilistic GE: each codon is read as a real value in [0, 1) and chooses a production
+    // by roulette wheel over `weights`, which must have been built from `grammar`. πGE order
+    // codons still pick a non-terminal by `codon % frontier_length`.
+    pub fn map_probabilistic(&self, grammar: &Grammar, genome: &[usize], weights: &ProductionWeights) -> MappingResult {
+        self.map_with(grammar, genome, Some(weights))
+    }
+
+    fn map_with(&self, grammar: &Grammar, genome: &[usize], weights: Option<&ProductionWeights>) -> MappingResult {
+        let mut codons = Codons { genome, max_wraps: self.max_wraps, used: 0, wraps: 0 };
+        let (phenotype, max_depth, status, tree) = match self.strategy {
+            MappingStrategy::DepthFirst => self.map_depth_first(grammar, weights, &mut codons),
+            _ => self.map_frontier(grammar, weights, &mut codons),
+        };
+
+        MappingResult {
+            phenotype,
+            used_codons: codons.used,
+            wraps: codons.wraps,
+            max_depth,
+            status,
+            tree,
+        }
+    }
+
+    // Leftmost derivation with an explicit stack, writing terminals as they are popped.
+    fn map_depth_first(
+        &self,
+        grammar: &Grammar,
+        weights: Option<&ProductionWeights>,
+        codons: &mut Codons,
+    ) -> (String, usize, MappingStatus, Option<DerivationTree>) {
+        let mut output: Vec<&str> = Vec::new();
+        let root = Symbol::NonTerminal(grammar.start());
+        let mut tree = self.build_tree.then(|| DerivationTree::new(root));
+        // Symbol, depth and derivation tree node (always 0 when no tree is built).
+        let mut stack = vec![(root, 1, 0)];
+        let mut max_depth = 0;
+        let mut status = MappingStatus::Valid;
+
+        while let Some((symbol, depth, node)) = stack.pop() {
+            let id = match symbol {
+                Symbol::Terminal(id) => {
+                    output.push(grammar.terminal(id));
+                    continue;
+                }
+                Symbol::NonTerminal(id) => id,
+            };
+
+            let codon = if self.max_depth.is_some_and(|limit| depth > limit) {
+                Err(InvalidReason::DepthLimit)
+            } else {
+                codons.next().ok_or(InvalidReason::WrapLimit)
+            };
+            let (index, codon) = match codon {
+                Ok(codon) => codon,
+                Err(reason) => {
+                    status = MappingStatus::Invalid(reason);
+                    stack.push((symbol, depth, node));
+                    break;
+                }
+            };
+
+            let productions = &grammar.rule(id).productions;
+            let choice = Choice { production: choose(grammar, weights, id, codon), codon: index };
+            let symbols = &productions[choice.production].symbols;
+            max_depth = max_depth.max(depth);
+
+            match tree.as_mut() {
+                Some(tree) => {
+                    let children = tree.expand(node, choice, symbols);
+                    stack.extend(symbols.iter().zip(children).rev().map(|(&symbol, child)| (symbol, depth + 1, child)));
+                }
+                None => stack.extend(symbols.iter().rev().map(|&symbol| (symbol, depth + 1, 0))),
+            }
+        }
+
+        let phenotype = partial_phenotype(grammar, &output, stack.iter().map(|&(symbol, _, _)| symbol));
+        (phenotype, max_depth, status, tree)
+    }
+
+    // The other strategies expand non-terminals out of sentential-form order, so they grow a
+    // derivation tree and read the phenotype off its leaves at the end.
+    fn map_frontier(
+        &self,
+        grammar: &Grammar,
+        weights: Option<&ProductionWeights>,
+        codons: &mut Codons,
+    ) -> (String, usize, MappingStatus, Option<DerivationTree>) {
+        let mut tree = DerivationTree::new(Symbol::NonTerminal(grammar.start()));
+        // Unexpanded non-terminal nodes, in left-to-right order except for breadth-first
+        // mapping, where it is a queue.
+        let mut frontier = vec![0];
+        let mut max_depth = 0;
+        let mut status = MappingStatus::Valid;
+
+        while !frontier.is_empty() {
+            let position = match self.strategy {
+                MappingStrategy::DepthFirst | MappingStrategy::BreadthFirst => Some(0),
+                MappingStrategy::RightMost => Some(frontier.len() - 1),
+                MappingStrategy::PiGE => codons.next().map(|(_, codon)| codon % frontier.len()),
+            };
+            let node = position.map(|position| frontier[position]);
+            let codon = match node {
+                Some(node) if self.max_depth.is_some_and(|limit| tree.nodes[node].depth > limit) => {
+                    Err(InvalidReason::DepthLimit)
+                }
+                Some(_) => codons.next().ok_or(InvalidReason::WrapLimit),
+                None => Err(InvalidReason::WrapLimit),
+            };
+            let (position, node, (index, codon)) = match codon {
+                Ok(codon) => (position.unwrap(), node.unwrap(), codon),
+                Err(reason) => {
+                    status = MappingStatus::Invalid(reason);
+                    break;
+                }
+            };
+
+            let Symbol::NonTerminal(id) = tree.nodes[node].symbol else {
+                unreachable!("the frontier only holds non-terminals")
+            };
+            let productions = &grammar.rule(id).productions;
+            let choice = Choice { production: choose(grammar, weights, id, codon), codon: index };
+            max_depth = max_depth.max(tree.nodes[node].depth);
+
+            let children = tree.expand(node, choice, &productions[choice.production].symbols);
+            let open = children.filter(|&child| matches!(tree.nodes[child].symbol, Symbol::NonTerminal(_)));
+            if self.strategy == MappingStrategy::BreadthFirst {
+                frontier.remove(position);
+                frontier.extend(open);
+            } else {
+                frontier.splice(position..=position, open);
+            }
+        }
+
+        let phenotype = tree.phenotype(grammar);
+        (phenotype, max_depth, status, self.build_tree.then_some(tree))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::parse_grammar_str;
+
+    fn boolean_grammar() -> Grammar {
+        parse_grammar_str("<S> ::= <E>\n<E> ::= <E> OR <T> | <T>\n<T> ::= <T> AND <F> | <F>\n<F> ::= NOT <F> | A | B | C")
+            .unwrap()
+    }
+
+    #[test]
+    fn test_map_with_wrapping() {
+        let result = Mapper::default().map(&boolean_grammar(), &[0, 1, 2, 3, 4]);
+        assert_eq!(result.phenotype, "NOT NOT A AND B");
+        assert!(result.is_valid());
+        assert_eq!((result.used_codons, result.wraps), (8, 1));
+        // S -> E -> T -> T -> F -> F -> F
+        assert_eq!(result.max_depth, 7);
+    }
+
+    #[test]
+    fn test_wrap_limit() {
+        let mapper = Mapper { max_wraps: 0, ..Mapper::default() };
+        let result = mapper.map(&boolean_grammar(), &[0, 1, 2, 3, 4]);
+        assert_eq!(result.status, MappingStatus::Invalid(InvalidReason::WrapLimit));
+        assert_eq!(result.phenotype, "NOT <F> AND <F>");
+        assert_eq!((result.used_codons, result.wraps), (5, 0));
+
+        // Always choosing `<E> OR <T>` never terminates, however often the genome wraps.
+        let result = Mapper::default().map(&boolean_grammar(), &[0]);
+        assert_eq!(result.status, MappingStatus::Invalid(InvalidReason::WrapLimit));
+        assert_eq!(result.wraps, DEFAULT_MAX_WRAPS);
+
+        let result = Mapper::default().map(&boolean_grammar(), &[]);
+        assert_eq!(result.status, MappingStatus::Invalid(InvalidReason::WrapLimit));
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        let grammar = boolean_grammar();
+        let mapper = Mapper { max_wraps: 0, max_depth: Some(5), ..Mapper::default() };
+        let result = mapper.map(&grammar, &[0, 1, 1, 0, 0, 1]);
+        assert_eq!(result.status, MappingStatus::Invalid(InvalidReason::DepthLimit));
+        assert_eq!(result.max_depth, 5);
+
+        let result = mapper.map(&grammar, &[0, 1, 1, 0, 1]);
+        assert!(result.is_valid());
+        assert_eq!((result.phenotype.as_str(), result.max_depth), ("NOT A", 5));
+    }
+
+    #[test]
+    fn test_derivation_tree() {
+        let grammar = boolean_grammar();
+        let mapper = Mapper { build_tree: true, ..Mapper::default() };
+        let result = mapper.map(&grammar, &[0, 1, 2, 3, 4]);
+        let tree = result.tree.unwrap();
+
+        assert_eq!(tree.phenotype(&grammar), result.phenotype);
+        assert_eq!(tree.depth(), result.max_depth);
+        assert_eq!(tree.root().choice, Some(Choice { production: 0, codon: 0 }));
+
+        // The innermost `<F>` is expanded by the first codon after wrapping round.
+        let choices: Vec<(String, Choice)> = tree
+            .preorder()
+            .into_iter()
+            .filter_map(|id| tree.nodes[id].choice.map(|choice| (grammar.symbol_name(tree.nodes[id].symbol).to_string(), choice)))
+            .collect();
+        let names: Vec<&str> = choices.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["S", "E", "T", "T", "F", "F", "F", "F"]);
+        let codons: Vec<usize> = choices.iter().map(|(_, choice)| choice.codon).collect();
+        assert_eq!(codons, vec![0, 1, 2, 3, 4, 0, 1, 2]);
+
+        let result = Mapper { max_wraps: 0, build_tree: true, ..Mapper::default() }.map(&grammar, &[0, 1, 2, 3, 4]);
+        assert!(!result.is_valid());
+        assert_eq!(result.tree.unwrap().phenotype(&grammar), result.phenotype);
+        assert!(Mapper::default().map(&grammar, &[0, 1, 2, 3, 4]).tree.is_none());
+    }
+
+    #[test]
+    fn test_derivation_tree_to_dot() {
+        let grammar = parse_grammar_str("<e> ::= <e> + <e> | 'x\"'").unwrap();
+        let mapper = Mapper { build_tree: true, ..Mapper::default() };
+        let dot = mapper.map(&grammar, &[0, 1, 1]).tree.unwrap().to_dot(&grammar);
+        assert!(dot.starts_with("digraph derivation {\n  n0 [label=\"<e> 0 @0\", shape=ellipse];\n  n0 -> n1;"));
+        assert!(dot.contains(r#"n4 [label="x\"", shape=plaintext]"#));
+    }
+
+    fn strategy_phenotype(strategy: MappingStrategy, genome: &[usize]) -> String {
+        let grammar = parse_grammar_str("<e> ::= <e> + <e> | x | y").unwrap();
+        let mapper = Mapper { max_wraps: 0, strategy, ..Mapper::default() };
+        let result = mapper.map(&grammar, genome);
+        assert!(result.is_valid());
+        result.phenotype
+    }
+
+    #[test]
+    fn test_mapping_strategies() {
+        let genome = [0, 0, 1, 2, 1, 2];
+        assert_eq!(strategy_phenotype(MappingStrategy::DepthFirst, &genome), "x + y + x");
+        assert_eq!(strategy_phenotype(MappingStrategy::BreadthFirst, &genome), "y + x + x");
+
+        let genome = [0, 0, 1, 2, 2];
+        assert_eq!(strategy_phenotype(MappingStrategy::DepthFirst, &genome), "x + y + y");
+        assert_eq!(strategy_phenotype(MappingStrategy::RightMost, &genome), "y + y + x");
+
+        // Order codon 1 picks the right `<e>` before the left one.
+        assert_eq!(strategy_phenotype(MappingStrategy::PiGE, &[0, 0, 1, 2, 2, 1]), "x + y");
+    }
+
+    #[test]
+    fn test_frontier_mapping_matches_depth_first() {
+        let grammar = boolean_grammar();
+        let genome = [0, 1, 2, 3, 4];
+        let mapper = Mapper { build_tree: true, ..Mapper::default() };
+        let mut codons = Codons { genome: &genome, max_wraps: DEFAULT_MAX_WRAPS, used: 0, wraps: 0 };
+        let (phenotype, max_depth, status, tree) = mapper.map_frontier(&grammar, None, &mut codons);
+        let expected = mapper.map(&grammar, &genome);
+        assert_eq!((phenotype, max_depth, status, tree), (expected.phenotype, expected.max_depth, expected.status, expected.tree));
+        assert_eq!((codons.used, codons.wraps), (expected.used_codons, expected.wraps));
+    }
+
+    #[test]
+    fn test_pige_limits() {
+        let grammar = boolean_grammar();
+        let mapper = Mapper { max_wraps: 0, strategy: MappingStrategy::PiGE, build_tree: true, ..Mapper::default() };
+        let result = mapper.map(&grammar, &[0, 0, 0, 1, 0]);
+        assert_eq!(result.status, MappingStatus::Invalid(InvalidReason::WrapLimit));
+        assert_eq!(result.used_codons, 5);
+        assert_eq!(result.phenotype, "<T>");
+
+        let mapper = Mapper { max_depth: Some(2), strategy: MappingStrategy::PiGE, ..Mapper::default() };
+        let result = mapper.map(&grammar, &[0, 0, 0, 1, 0, 0]);
+        assert_eq!(result.status, MappingStatus::Invalid(InvalidReason::DepthLimit));
+        assert_eq!(result.max_depth, 2);
+    }
+
+    #[test]
+    fn test_map_probabilistic() {
+        let grammar = parse_grammar_str("<e> ::= <e> + <e> [0.25] | x [0.5] | y [0.25]").unwrap();
+        let weights = ProductionWeights::new(&grammar);
+        // 10/256 falls in `<e> + <e>`, 100/256 in `x` and 200/256 in `y`.
+        let genome = [10, 100, 200];
+        assert_eq!(Mapper::default().map(&grammar, &genome).phenotype, "x");
+        for strategy in [MappingStrategy::DepthFirst, MappingStrategy::BreadthFirst] {
+            let mapper = Mapper { max_wraps: 0, strategy, ..Mapper::default() };
+            let result = mapper.map_probabilistic(&grammar, &genome, &weights);
+            assert!(result.is_valid());
+            assert_eq!(result.phenotype, "x + y");
+        }
+    }
+}
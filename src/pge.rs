@@ -0,0 +1,154 @@
+use std::ops::Range;
+
+use crate::derivation::DerivationTree;
+use crate::genome::MAX_GENE_VALUE;
+use crate::grammar::{Grammar, Symbol};
+
+// How far each update moves the weights towards the productions the best individuals used.
+pub const DEFAULT_LEARNING_FACTOR: f64 = 0.01;
+
+// Reads a codon as a real value in [0, 1).
+pub fn codon_value(codon: usize) -> f64 {
+    (codon % (MAX_GENE_VALUE + 1)) as f64 / (MAX_GENE_VALUE + 1) as f64
+}
+
+// Production probabilities for probabilistic GE, one list per rule of the grammar they were
+// taken from. They start out as the weights written in the BNF and can then be learned.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProductionWeights {
+    weights: Vec<Vec<f64>>,
+}
+
+impl ProductionWeights {
+    pub fn new(grammar: &Grammar) -> Self {
+        ProductionWeights {
+            weights: grammar
+                .rules()
+                .iter()
+                .map(|rule| rule.productions.iter().map(|production| production.weight).collect())
+                .collect(),
+        }
+    }
+
+    pub fn weights(&self, rule: usize) -> &[f64] {
+        &self.weights[rule]
+    }
+
+    pub fn weight(&self, rule: usize, production: usize) -> f64 {
+        self.weights[rule][production]
+    }
+
+    // Roulette-wheel choice of a production of `rule`, with the codon as the spin.
+    pub fn choose(&self, rule: usize, codon: usize) -> usize {
+        let value = codon_value(codon);
+        let weights = &self.weights[rule];
+        let mut cumulative = 0.0;
+        for (production, weight) in weights.iter().enumerate() {
+            cumulative += weight;
+            if value < cumulative {
+                return production;
+            }
+        }
+        // Rounding can leave the total just under 1.
+        weights.iter().rposition(|&weight| weight > 0.0).unwrap_or(weights.len() - 1)
+    }
+
+    // The codons in the gene range that choose `production` of `rule`. They are consecutive,
+    // and there are none when the weight is too small for any codon to land on it.
+    pub fn codons(&self, rule: usize, production: usize) -> Range<usize> {
+        // `choose` never decreases as the codon grows, so both ends are found by bisection.
+        let end = |inclusive: bool| {
+            let (mut low, mut high) = (0, MAX_GENE_VALUE + 1);
+            while low < high {
+                let middle = (low + high) / 2;
+                let chosen = self.choose(rule, middle);
+                if chosen < production || (inclusive && chosen == production) {
+                    low = middle + 1;
+                } else {
+                    high = middle;
+                }
+            }
+            low
+        };
+        end(false)..end(true)
+    }
+
+    // PGE update from the derivation trees of the best individuals. For every rule they
+    // expanded, a production used `count` of the rule's `total` expansions becomes
+    // `min(p + learning_factor * count / total, 1)`, an unused one `(1 - learning_factor) * p`,
+    // and the rule is renormalised. Rules the trees never expanded keep their weights.
+    pub fn update<'a>(&mut self, trees: impl IntoIterator<Item = &'a DerivationTree>, learning_factor: f64) {
+        let mut counts: Vec<Vec<usize>> = self.weights.iter().map(|weights| vec![0; weights.len()]).collect();
+        for tree in trees {
+            for node in &tree.nodes {
+                if let (Symbol::NonTerminal(rule), Some(choice)) = (node.symbol, node.choice) {
+                    counts[rule][choice.production] += 1;
+                }
+            }
+        }
+
+        for (weights, counts) in self.weights.iter_mut().zip(counts) {
+            let total: usize = counts.iter().sum();
+            if total == 0 {
+                continue;
+            }
+            for (weight, count) in weights.iter_mut().zip(counts) {
+                *weight = if count > 0 {
+                    (*weight + learning_factor * count as f64 / total as f64).min(1.0)
+                } else {
+                    (1.0 - learning_factor) * *weight
+                };
+            }
+            let sum: f64 = weights.iter().sum();
+            weights.iter_mut().for_each(|weight| *weight /= sum);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::{parse_bnf, parse_grammar_str};
+    use crate::mapper::Mapper;
+
+    #[test]
+    fn test_choose() {
+        let grammar = parse_grammar_str("<e> ::= <e> + <e> [0.25] | x [0.75]").unwrap();
+        let weights = ProductionWeights::new(&grammar);
+        assert_eq!(weights.weights(0), &[0.25, 0.75]);
+        assert_eq!(weights.choose(0, 0), 0);
+        assert_eq!(weights.choose(0, 63), 0);
+        assert_eq!(weights.choose(0, 64), 1);
+        assert_eq!(weights.choose(0, 255), 1);
+        // Codons above the gene range wrap round like any other.
+        assert_eq!(weights.choose(0, 256), 0);
+        assert_eq!((weights.codons(0, 0), weights.codons(0, 1)), (0..64, 64..256));
+
+        // No codon value lies between 0.501 and 0.502.
+        let grammar = parse_grammar_str("<e> ::= a [0.501] | b [0.001] | c [0] | d [0.498]").unwrap();
+        let weights = ProductionWeights::new(&grammar);
+        assert_eq!(weights.codons(0, 0), 0..129);
+        assert!(weights.codons(0, 1).is_empty() && weights.codons(0, 2).is_empty());
+        assert_eq!(weights.codons(0, 3), 129..256);
+    }
+
+    #[test]
+    fn test_update() {
+        // `<c>` is unreachable, so the grammar is not validated.
+        let grammar = Grammar::from_rules(&parse_bnf("<e> ::= <e> + <e> | x | y\n<c> ::= 0 | 1").unwrap()).unwrap();
+        let mut weights = ProductionWeights::new(&grammar);
+        let mapper = Mapper { build_tree: true, ..Mapper::default() };
+        // x + x: one `<e> + <e>` and two `x`.
+        let tree = mapper.map(&grammar, &[0, 1, 1]).tree.unwrap();
+
+        weights.update([&tree], 0.5);
+        let e = weights.weights(grammar.non_terminal_id("e").unwrap());
+        let expected = [1.0 / 3.0 + 0.5 / 3.0, 1.0 / 3.0 + 0.5 * 2.0 / 3.0, 0.5 / 3.0];
+        let sum: f64 = expected.iter().sum();
+        for (weight, expected) in e.iter().zip(expected) {
+            assert!((weight - expected / sum).abs() < 1e-12);
+        }
+        assert!(e[1] > e[0] && e[0] > e[2]);
+        assert_eq!(weights.weights(grammar.non_terminal_id("c").unwrap()), &[0.5, 0.5]);
+    }
+}
@@ -1,60 +1,559 @@
-use rand::Rng;
-use crate::evolutionary_ops::{mutate, tournament_selection, one_point_crossover};
+use std::cmp::Ordering;
+use std::io::{self, Write};
 
-type Genome = Vec<usize>;
-type Population = Vec<Genome>;
+use rand::Rng;
+use crate::evolutionary_ops::{mutate, tournament_index, tournament_selection, one_point_crossover, TieBreak};
+use crate::fitness::{Direction, Fitness};
+use crate::individual::{IdGenerator, Individual, Population};
 
 const POPULATION_SIZE: usize = 100;
 // const MAX_GENERATIONS: usize = 1000;
 const ELITISM_COUNT: usize = 5;
+const TOURNAMENT_SIZE: usize = 2;
+
+// How each generation replaces the previous one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Replacement {
+    // A whole new population is bred every generation; only the elite survive.
+    #[default]
+    Generational,
+    // Offspring are bred `offspring` (1 or 2) at a time and immediately take the place of
+    // individuals chosen by `policy`, so they can be selected as parents straight away. A
+    // generation is over once as many offspring as the population size have been bred.
+    SteadyState { offspring: usize, policy: ReplacementPolicy },
+    // NSGA-II: parents are chosen by binary tournament on Pareto front and crowding distance,
+    // and the next generation is the best of parents and offspring together in the same
+    // order. For fitness values with several objectives, such as `MultiObjective`.
+    Nsga2,
+}
+
+// Which individual a steady-state offspring replaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReplacementPolicy {
+    // The least fit individual.
+    #[default]
+    Worst,
+    // A uniformly random individual.
+    Random,
+    // The loser of a tournament of the given size.
+    InverseTournament(usize),
+    // The parent the offspring was bred from: the first offspring replaces the first parent
+    // and the second the second. When both parents are the same individual only the first
+    // offspring is kept.
+    Parent,
+}
 
 // Initialize a random population
-pub fn initialize_population(size: usize) -> Population {
+pub fn initialize_population<F: Fitness>(size: usize, ids: &mut IdGenerator, rng: &mut impl Rng) -> Population<F> {
     (0..size).map(|_| {
-        let mut rng = rand::thread_rng();
-        (0..rng.gen_range(1..100)).map(|_| rng.gen_range(0..256)).collect()
+        Individual::offspring((0..rng.gen_range(1..100)).map(|_| rng.gen_range(0..256)).collect(), &[], ids)
     }).collect()
 }
 
-pub fn random_initialization(pop_size: usize, genome_length: usize, max_gene_value: usize) -> Population {
-    let mut rng = rand::thread_rng();
-    let mut population: Vec<Vec<usize>> = Vec::with_capacity(POPULATION_SIZE);
+pub fn random_initialization<F: Fitness>(
+    pop_size: usize,
+    genome_length: usize,
+    max_gene_value: usize,
+    ids: &mut IdGenerator,
+    rng: &mut impl Rng,
+) -> Population<F> {
+    let mut population: Population<F> = Vec::with_capacity(pop_size);
 
     for _ in 0..pop_size {
         let individual: Vec<usize> = (0..genome_length)
             .map(|_| rng.gen_range(0..max_gene_value))
             .collect();
-        population.push(individual);
+        population.push(Individual::offspring(individual, &[], ids));
     }
 
     population
 }
 
-// Evolve the population for one generation
-pub fn evolve_population(population: &Population, fitness: &dyn Fn(&Vec<usize>) -> usize) -> Population {
-    let mut new_population = Vec::with_capacity(POPULATION_SIZE);
+// Several subpopulations (islands) evolved independently, exchanging a few individuals every
+// `migration_interval` generations.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IslandModel {
+    pub islands: usize,
+    pub migration_interval: usize,
+    // Individuals each island sends to each of its destinations.
+    pub migrants: usize,
+    pub emigrants: MigrantSelection,
+    // Individuals the migrants replace on the receiving island. `ReplacementPolicy::Parent`
+    // does not apply to migrants, which are then dropped.
+    pub immigrants: ReplacementPolicy,
+    pub topology: Topology,
+}
+
+impl Default for IslandModel {
+    fn default() -> Self {
+        IslandModel {
+            islands: 4,
+            migration_interval: 10,
+            migrants: 2,
+            emigrants: MigrantSelection::Best,
+            immigrants: ReplacementPolicy::Worst,
+            topology: Topology::Ring,
+        }
+    }
+}
+
+// Which individuals leave an island. Migrants are copies; the originals stay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MigrantSelection {
+    #[default]
+    Best,
+    // Distinct individuals chosen uniformly at random.
+    Random,
+    // Winners of tournaments of the given size.
+    Tournament(usize),
+}
+
+// Where migrants go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Topology {
+    // Island `i` sends to island `i + 1`, and the last to the first.
+    #[default]
+    Ring,
+    // Every island sends to every other island.
+    FullyConnected,
+    // Every island sends to one other island chosen at random at each migration.
+    Random,
+}
+
+// Moves migrants between islands. Migrants are all chosen before any island receives any, so
+// an individual never moves twice in one migration.
+pub fn migrate<F: Fitness>(
+    islands: &mut [Population<F>],
+    ids: &mut [IdGenerator],
+    model: &IslandModel,
+    elite_size: usize,
+    direction: Direction,
+    rng: &mut impl Rng,
+) {
+    let count = islands.len();
+    if count < 2 {
+        return;
+    }
+
+    let mut arrivals: Vec<Population<F>> = vec![Vec::new(); count];
+    for (source, population) in islands.iter().enumerate() {
+        let migrants = emigrants(population, model.migrants, model.emigrants, direction, rng);
+        let destinations: Vec<usize> = match model.topology {
+            Topology::Ring => vec![(source + 1) % count],
+            Topology::FullyConnected => (0..count).filter(|&island| island != source).collect(),
+            Topology::Random => vec![(source + rng.gen_range(1..count)) % count],
+        };
+        for destination in destinations {
+            arrivals[destination].extend(migrants.iter().map(|migrant| migrant.copy(&mut ids[destination])));
+        }
+    }
+
+    for (population, migrants) in islands.iter_mut().zip(arrivals) {
+        replace(population, migrants, &[], model.immigrants, elite_size, direction, rng);
+    }
+}
 
-    // Sort by fitness
-    let mut sorted_population = population.clone();
-    sorted_population.sort_by_key(|genome| fitness(genome));
+fn emigrants<F: Fitness>(
+    population: &[Individual<F>],
+    count: usize,
+    selection: MigrantSelection,
+    direction: Direction,
+    rng: &mut impl Rng,
+) -> Population<F> {
+    let indices = match selection {
+        MigrantSelection::Best => elite(population, count, direction),
+        MigrantSelection::Random => rand::seq::index::sample(rng, population.len(), count.min(population.len())).into_vec(),
+        MigrantSelection::Tournament(size) => {
+            (0..count).map(|_| tournament_index(population, size, direction, TieBreak::Random, rng)).collect()
+        }
+    };
+    indices.into_iter().map(|index| population[index].clone()).collect()
+}
+
+// Indices of the `count` fittest individuals, best first.
+pub fn elite<F: Fitness>(population: &[Individual<F>], count: usize, direction: Direction) -> Vec<usize> {
+    let mut ranking: Vec<usize> = (0..population.len()).collect();
+    ranking.sort_by(|&a, &b| population[a].compare(&population[b], direction));
+    ranking.truncate(count);
+    ranking
+}
+
+// Puts evaluated `offspring` into the population in place of individuals chosen by `policy`.
+// `parents` are the indices of the individuals the offspring were bred from, used by
+// `ReplacementPolicy::Parent`. The `elite_size` fittest individuals are never replaced, and
+// neither is an offspring inserted by the same call; offspring with nothing left to replace
+// are dropped.
+pub fn replace<F: Fitness>(
+    population: &mut Population<F>,
+    offspring: Population<F>,
+    parents: &[usize],
+    policy: ReplacementPolicy,
+    elite_size: usize,
+    direction: Direction,
+    rng: &mut impl Rng,
+) {
+    let mut replaceable = vec![true; population.len()];
+    for index in elite(population, elite_size, direction) {
+        replaceable[index] = false;
+    }
+
+    for (k, child) in offspring.into_iter().enumerate() {
+        let candidates: Vec<usize> = (0..population.len()).filter(|&index| replaceable[index]).collect();
+        if candidates.is_empty() {
+            return;
+        }
+        let victim = match policy {
+            ReplacementPolicy::Worst => candidates
+                .iter()
+                .copied()
+                .max_by(|&a, &b| population[a].compare(&population[b], direction))
+                .unwrap(),
+            ReplacementPolicy::Random => candidates[rng.gen_range(0..candidates.len())],
+            ReplacementPolicy::InverseTournament(size) => {
+                let mut loser = candidates[rng.gen_range(0..candidates.len())];
+                for _ in 1..size {
+                    let contender = candidates[rng.gen_range(0..candidates.len())];
+                    if population[contender].compare(&population[loser], direction).is_gt() {
+                        loser = contender;
+                    }
+                }
+                loser
+            }
+            ReplacementPolicy::Parent => match parents.get(k) {
+                Some(&parent) if replaceable[parent] => parent,
+                _ => continue,
+            },
+        };
+        population[victim] = child;
+        replaceable[victim] = false;
+    }
+}
+
+// Evaluates the individuals that have no fitness yet.
+pub fn evaluate_population<F: Fitness>(population: &mut Population<F>, fitness: &dyn Fn(&Individual<F>) -> F) {
+    for individual in population.iter_mut() {
+        individual.evaluate(fitness);
+    }
+}
 
-    // Elitism: directly carry over the best genomes
-    for i in 0..ELITISM_COUNT {
-        new_population.push(sorted_population[i].clone());
+// Evolve the population for one generation. Individuals are evaluated at most once; the best
+// `ELITISM_COUNT` are carried over and the rest of the new population is bred from parents
+// chosen by tournament.
+pub fn evolve_population<F: Fitness>(
+    population: &mut Population<F>,
+    fitness: &dyn Fn(&Individual<F>) -> F,
+    direction: Direction,
+    ids: &mut IdGenerator,
+    rng: &mut impl Rng,
+) -> Population<F> {
+    let mut new_population = Vec::with_capacity(POPULATION_SIZE);
+    evaluate_population(population, fitness);
+
+    // Elitism: directly carry over the best individuals
+    for index in elite(population, ELITISM_COUNT, direction) {
+        new_population.push(population[index].survivor());
     }
 
     // Rest of the new population is filled by offspring from crossover and mutation
     while new_population.len() < POPULATION_SIZE {
-        let parent1 = tournament_selection(&sorted_population, 2);
-        let parent2 = tournament_selection(&sorted_population, 2);
-        let (mut child1, mut child2) = one_point_crossover(parent1, parent2);
+        let parent1 = tournament_selection(population, TOURNAMENT_SIZE, direction, TieBreak::Random, rng);
+        let parent2 = tournament_selection(population, TOURNAMENT_SIZE, direction, TieBreak::Random, rng);
+        let (mut child1, mut child2) = one_point_crossover(&parent1.genome, &parent2.genome, rng);
 
-        mutate(&mut child1);
-        mutate(&mut child2);
+        mutate(&mut child1, rng);
+        mutate(&mut child2, rng);
 
-        new_population.push(child1);
-        new_population.push(child2);
+        new_population.push(Individual::offspring(child1, &[parent1, parent2], ids));
+        new_population.push(Individual::offspring(child2, &[parent1, parent2], ids));
     }
+    new_population.truncate(POPULATION_SIZE);
 
     new_population
-}
\ No newline at end of file
+}
+
+// Where an individual stands in NSGA-II: its Pareto front (0 for the non-dominated one) and its
+// crowding distance within that front.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParetoRank {
+    pub front: usize,
+    pub crowding: f64,
+}
+
+impl ParetoRank {
+    // `Ordering::Less` when `self` is better: an earlier front, or a less crowded spot on the
+    // same front.
+    pub fn compare(&self, other: &ParetoRank) -> Ordering {
+        self.front.cmp(&other.front).then_with(|| other.crowding.total_cmp(&self.crowding))
+    }
+}
+
+// Pareto dominance between individuals, with unevaluated individuals dominated by every
+// evaluated one.
+fn dominates<F: Fitness>(a: &Individual<F>, b: &Individual<F>, direction: Direction) -> bool {
+    match (&a.fitness, &b.fitness) {
+        (Some(a), Some(b)) => a.dominates(b, direction),
+        (Some(_), None) => true,
+        _ => false,
+    }
+}
+
+// Indices of the population split into fronts: the first holds the individuals no one
+// dominates, the second those dominated only by the first, and so on.
+pub fn non_dominated_sort<F: Fitness>(population: &[Individual<F>], direction: Direction) -> Vec<Vec<usize>> {
+    let mut dominated: Vec<Vec<usize>> = vec![Vec::new(); population.len()];
+    let mut domination_count = vec![0; population.len()];
+    for a in 0..population.len() {
+        for b in a + 1..population.len() {
+            if dominates(&population[a], &population[b], direction) {
+                dominated[a].push(b);
+                domination_count[b] += 1;
+            } else if dominates(&population[b], &population[a], direction) {
+                dominated[b].push(a);
+                domination_count[a] += 1;
+            }
+        }
+    }
+
+    let mut fronts = Vec::new();
+    let mut front: Vec<usize> = (0..population.len()).filter(|&index| domination_count[index] == 0).collect();
+    while !front.is_empty() {
+        let mut next = Vec::new();
+        for &a in &front {
+            for &b in &dominated[a] {
+                domination_count[b] -= 1;
+                if domination_count[b] == 0 {
+                    next.push(b);
+                }
+            }
+        }
+        next.sort_unstable();
+        fronts.push(front);
+        front = next;
+    }
+    fronts
+}
+
+// Crowding distance of each individual of `front`: the sum over objectives of the normalised
+// distance between its neighbours on either side. The extremes of each objective get an
+// infinite distance so that they are always kept.
+pub fn crowding_distances<F: Fitness>(population: &[Individual<F>], front: &[usize]) -> Vec<f64> {
+    let objectives: Vec<Vec<f64>> = front
+        .iter()
+        .map(|&index| population[index].fitness.as_ref().map_or_else(Vec::new, Fitness::objectives))
+        .collect();
+    let count = objectives.iter().map(Vec::len).max().unwrap_or(0);
+    let mut distances = vec![0.0; front.len()];
+
+    for objective in 0..count {
+        let value = |i: usize| objectives[i].get(objective).copied().unwrap_or(f64::NAN);
+        let mut order: Vec<usize> = (0..front.len()).collect();
+        order.sort_by(|&a, &b| value(a).total_cmp(&value(b)));
+        let (first, last) = (order[0], order[order.len() - 1]);
+        distances[first] = f64::INFINITY;
+        distances[last] = f64::INFINITY;
+        let range = value(last) - value(first);
+        if !range.is_finite() || range == 0.0 {
+            continue;
+        }
+        for window in order.windows(3) {
+            let gap = (value(window[2]) - value(window[0])) / range;
+            if gap.is_finite() {
+                distances[window[1]] += gap;
+            }
+        }
+    }
+    distances
+}
+
+// The Pareto rank of every individual.
+pub fn pareto_ranks<F: Fitness>(population: &[Individual<F>], direction: Direction) -> Vec<ParetoRank> {
+    let mut ranks = vec![ParetoRank { front: 0, crowding: 0.0 }; population.len()];
+    for (front_index, front) in non_dominated_sort(population, direction).iter().enumerate() {
+        for (&index, crowding) in front.iter().zip(crowding_distances(population, front)) {
+            ranks[index] = ParetoRank { front: front_index, crowding };
+        }
+    }
+    ranks
+}
+
+// Binary tournament on Pareto rank, returning the index of the winner.
+pub fn crowded_tournament_index(ranks: &[ParetoRank], rng: &mut impl Rng) -> usize {
+    let a = rng.gen_range(0..ranks.len());
+    let b = rng.gen_range(0..ranks.len());
+    if ranks[b].compare(&ranks[a]).is_lt() { b } else { a }
+}
+
+// NSGA-II survival: the `size` best of `population` by Pareto rank, taking whole fronts while
+// they fit and the least crowded individuals of the front that does not.
+pub fn nsga2_survivors<F: Fitness>(population: Population<F>, size: usize, direction: Direction) -> Population<F> {
+    let mut chosen = Vec::with_capacity(size);
+    for front in non_dominated_sort(&population, direction) {
+        if chosen.len() + front.len() <= size {
+            chosen.extend(front);
+            continue;
+        }
+        let distances = crowding_distances(&population, &front);
+        let mut order: Vec<usize> = (0..front.len()).collect();
+        order.sort_by(|&a, &b| distances[b].total_cmp(&distances[a]));
+        chosen.extend(order.into_iter().take(size - chosen.len()).map(|i| front[i]));
+        break;
+    }
+    chosen.sort_unstable();
+
+    let mut population: Vec<Option<Individual<F>>> = population.into_iter().map(Some).collect();
+    chosen.into_iter().map(|index| population[index].take().unwrap()).collect()
+}
+
+// The individuals of the first front, with duplicates of the same fitness and phenotype
+// removed, ordered by fitness.
+pub fn pareto_front<F: Fitness>(population: &[Individual<F>], direction: Direction) -> Vec<&Individual<F>> {
+    let mut front: Vec<&Individual<F>> = Vec::new();
+    for &index in non_dominated_sort(population, direction).first().into_iter().flatten() {
+        let individual = &population[index];
+        if !front.iter().any(|kept| kept.fitness == individual.fitness && kept.phenotype == individual.phenotype) {
+            front.push(individual);
+        }
+    }
+    front.sort_by(|a, b| a.compare(b, direction));
+    front
+}
+
+// Writes a front as CSV: one column per objective, then the phenotype.
+pub fn export_front<F: Fitness>(front: &[&Individual<F>], writer: &mut impl Write) -> io::Result<()> {
+    let objectives = front
+        .iter()
+        .filter_map(|individual| individual.fitness.as_ref())
+        .map(|fitness| fitness.objectives().len())
+        .max()
+        .unwrap_or(0);
+    let header: Vec<String> = (1..=objectives).map(|objective| format!("objective_{}", objective)).collect();
+    writeln!(writer, "{},phenotype", header.join(","))?;
+    for individual in front {
+        let objectives = individual.fitness.as_ref().map_or_else(Vec::new, Fitness::objectives);
+        let values: Vec<String> = objectives.iter().map(f64::to_string).collect();
+        let phenotype = individual.phenotype.as_deref().unwrap_or_default().replace('"', "\"\"");
+        writeln!(writer, "{},\"{}\"", values.join(","), phenotype)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fitness::MultiObjective;
+    use crate::random;
+
+    #[test]
+    fn test_evolve_population_keeps_elites() {
+        let mut rng = random::seeded(5);
+        let mut ids = IdGenerator::new(0, 1);
+        let mut population = initialize_population(POPULATION_SIZE, &mut ids, &mut rng);
+        let fitness = |individual: &Individual| {
+            individual.genome.iter().sum::<usize>() as f64 / individual.genome.len() as f64
+        };
+        let best = population.iter().map(fitness).fold(f64::INFINITY, f64::min);
+
+        let next = evolve_population(&mut population, &fitness, Direction::Minimize, &mut ids, &mut rng);
+        assert!(population.iter().all(Individual::is_evaluated));
+        assert_eq!(next.len(), POPULATION_SIZE);
+        assert_eq!((next[0].fitness, next[0].age), (Some(best), 1));
+        assert!(next[ELITISM_COUNT..].iter().all(|individual| individual.fitness.is_none() && individual.parents.len() == 2));
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut rng = random::seeded(6);
+        let evaluated = |fitness: f64| Individual { fitness: Some(fitness), ..Individual::new(Vec::new()) };
+        let fitnesses = |population: &Population| -> Vec<f64> { population.iter().map(|individual| individual.fitness.unwrap()).collect() };
+        let population: Population = [3.0, 1.0, 4.0, 2.0].into_iter().map(evaluated).collect();
+        assert_eq!(elite(&population, 2, Direction::Minimize), vec![1, 3]);
+
+        let mut worst = population.clone();
+        replace(&mut worst, vec![evaluated(9.0), evaluated(8.0)], &[], ReplacementPolicy::Worst, 1, Direction::Minimize, &mut rng);
+        // The second offspring does not replace the first, even though it is now the worst.
+        assert_eq!(fitnesses(&worst), vec![8.0, 1.0, 9.0, 2.0]);
+
+        let mut parent = population.clone();
+        replace(&mut parent, vec![evaluated(5.0), evaluated(6.0)], &[1, 0], ReplacementPolicy::Parent, 1, Direction::Minimize, &mut rng);
+        // Parent 1 is the elite, so the first offspring is dropped.
+        assert_eq!(fitnesses(&parent), vec![6.0, 1.0, 4.0, 2.0]);
+
+        for policy in [ReplacementPolicy::Random, ReplacementPolicy::InverseTournament(3)] {
+            for _ in 0..20 {
+                let mut replaced = population.clone();
+                replace(&mut replaced, vec![evaluated(0.0)], &[], policy, 3, Direction::Minimize, &mut rng);
+                assert_eq!(fitnesses(&replaced), vec![3.0, 1.0, 0.0, 2.0]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_migrate() {
+        let mut rng = random::seeded(7);
+        let mut ids: Vec<IdGenerator> = (0..3).map(|island| IdGenerator::new(island, 3)).collect();
+        let evaluated = |fitness: f64| Individual { fitness: Some(fitness), ..Individual::new(Vec::new()) };
+        let island = |offset: f64| -> Population { (0..4).map(|i| evaluated(offset + i as f64)).collect() };
+        let fitnesses = |population: &Population| -> Vec<f64> { population.iter().map(|individual| individual.fitness.unwrap()).collect() };
+
+        // Each island's best replaces the worst of the next one.
+        let mut islands = vec![island(0.0), island(10.0), island(20.0)];
+        let model = IslandModel { islands: 3, migrants: 1, ..IslandModel::default() };
+        migrate(&mut islands, &mut ids, &model, 0, Direction::Minimize, &mut rng);
+        assert_eq!(fitnesses(&islands[0]), vec![0.0, 1.0, 2.0, 20.0]);
+        assert_eq!(fitnesses(&islands[1]), vec![10.0, 11.0, 12.0, 0.0]);
+        assert_eq!(fitnesses(&islands[2]), vec![20.0, 21.0, 22.0, 10.0]);
+        // Migrants are copies with an id from the island they arrive on.
+        assert_eq!((islands[1][3].id, &islands[1][3].parents), (2, &vec![islands[0][0].id]));
+
+        let mut islands = vec![island(0.0), island(10.0), island(20.0)];
+        let model = IslandModel { islands: 3, migrants: 1, topology: Topology::FullyConnected, ..IslandModel::default() };
+        migrate(&mut islands, &mut ids, &model, 0, Direction::Minimize, &mut rng);
+        assert_eq!(fitnesses(&islands[2]), vec![20.0, 21.0, 10.0, 0.0]);
+
+        // The two elite stay. Each island sends to one other, so at least two islands receive
+        // migrants, which replace both of their other individuals.
+        let mut islands = vec![island(0.0), island(10.0), island(20.0)];
+        let model = IslandModel { islands: 3, emigrants: MigrantSelection::Random, topology: Topology::Random, ..IslandModel::default() };
+        migrate(&mut islands, &mut ids, &model, 2, Direction::Minimize, &mut rng);
+        let mut receivers = 0;
+        for (i, population) in islands.iter().enumerate() {
+            let origins: Vec<usize> = fitnesses(population).iter().map(|fitness| (fitness / 10.0) as usize).collect();
+            assert_eq!(origins[..2], [i, i]);
+            if origins[2..].iter().all(|&origin| origin != i) {
+                receivers += 1;
+            } else {
+                assert_eq!(origins[2..], [i, i]);
+            }
+        }
+        assert!(receivers >= 2);
+    }
+
+    #[test]
+    fn test_nsga2() {
+        let evaluated = |objectives: &[f64]| Individual {
+            fitness: Some(MultiObjective(objectives.to_vec())),
+            phenotype: Some(format!("{:?}", objectives)),
+            ..Individual::new(Vec::new())
+        };
+        let population: Population<MultiObjective> =
+            [[1.0, 5.0], [2.0, 3.0], [4.0, 1.0], [4.5, 2.0], [5.0, 5.0], [2.5, 3.0]].iter().map(|o| evaluated(o)).collect();
+
+        assert_eq!(non_dominated_sort(&population, Direction::Minimize), vec![vec![0, 1, 2], vec![3, 5], vec![4]]);
+        assert_eq!(crowding_distances(&population, &[0, 1, 2]), vec![f64::INFINITY, 2.0, f64::INFINITY]);
+        let ranks = pareto_ranks(&population, Direction::Minimize);
+        assert_eq!((ranks[4].front, ranks[1].crowding), (2, 2.0));
+        assert!(ranks[1].compare(&ranks[3]).is_lt());
+
+        let survivors = nsga2_survivors(population.clone(), 4, Direction::Minimize);
+        assert_eq!(survivors.len(), 4);
+        assert_eq!(survivors[..3], population[..3]);
+
+        let front = pareto_front(&population, Direction::Minimize);
+        let mut csv = Vec::new();
+        export_front(&front, &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "objective_1,objective_2,phenotype\n1,5,\"[1.0, 5.0]\"\n2,3,\"[2.0, 3.0]\"\n4,1,\"[4.0, 1.0]\"\n"
+        );
+    }
+}
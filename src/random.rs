@@ -0,0 +1,37 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+// The random number generator used for reproducible runs. Its output for a given seed and
+// stream is fixed, independent of platform and of the `rand` version.
+pub type EvolutionRng = ChaCha8Rng;
+
+pub fn seeded(seed: u64) -> EvolutionRng {
+    EvolutionRng::seed_from_u64(seed)
+}
+
+// One of 2^64 independent streams for `seed`, e.g. one per run or per island.
+pub fn stream(seed: u64, stream: u64) -> EvolutionRng {
+    let mut rng = seeded(seed);
+    rng.set_stream(stream);
+    rng
+}
+
+// A fresh seed from the operating system, for runs that are not given one.
+pub fn random_seed() -> u64 {
+    rand::random()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_streams() {
+        let draw = |mut rng: EvolutionRng| -> Vec<u32> { (0..8).map(|_| rng.gen()).collect() };
+        assert_eq!(draw(seeded(7)), draw(stream(7, 0)));
+        assert_eq!(draw(stream(7, 3)), draw(stream(7, 3)));
+        assert_ne!(draw(stream(7, 3)), draw(stream(7, 4)));
+        assert_ne!(draw(seeded(7)), draw(seeded(8)));
+    }
+}
@@ -0,0 +1,255 @@
+use std::collections::{HashMap, HashSet};
+
+use rand::Rng;
+
+use crate::genome::Genome;
+use crate::grammar::{Grammar, Symbol};
+use crate::initialization::encode_choice;
+
+// Earley item: production `production` of `rule`, with `dot` symbols matched from `origin`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Item {
+    rule: usize,
+    production: usize,
+    dot: usize,
+    origin: usize,
+}
+
+// Parse of a phenotype against a grammar. Phenotypes are read the way the mapper writes them:
+// terminals separated by single spaces. Positions are byte offsets into the phenotype with a
+// space in front, so that every terminal is matched together with the space before it.
+pub struct PhenotypeParse<'a> {
+    grammar: &'a Grammar,
+    text: String,
+    // (rule, production, start, end) of every complete production in the chart.
+    completed: HashSet<(usize, usize, usize, usize)>,
+    // Ends of the complete spans of each (rule, start).
+    ends: HashMap<(usize, usize), Vec<usize>>,
+}
+
+impl<'a> PhenotypeParse<'a> {
+    // Earley recogniser over the characters of the phenotype. The grammar is expected to have
+    // passed `Grammar::validate`, so no production is empty.
+    pub fn new(grammar: &'a Grammar, phenotype: &str) -> Self {
+        let text = format!(" {}", phenotype);
+        let mut sets: Vec<Vec<Item>> = vec![Vec::new(); text.len() + 1];
+        let mut seen: Vec<HashSet<Item>> = vec![HashSet::new(); text.len() + 1];
+        let mut completed = HashSet::new();
+        let mut ends: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+
+        let start = grammar.start();
+        for production in 0..grammar.rule(start).productions.len() {
+            let item = Item { rule: start, production, dot: 0, origin: 0 };
+            seen[0].insert(item);
+            sets[0].push(item);
+        }
+
+        for position in 0..sets.len() {
+            let mut i = 0;
+            while i < sets[position].len() {
+                let item = sets[position][i];
+                i += 1;
+                let symbols = &grammar.rule(item.rule).productions[item.production].symbols;
+                let mut add = |set: usize, item: Item, sets: &mut Vec<Vec<Item>>| {
+                    if seen[set].insert(item) {
+                        sets[set].push(item);
+                    }
+                };
+
+                match symbols.get(item.dot) {
+                    None => {
+                        completed.insert((item.rule, item.production, item.origin, position));
+                        let spans = ends.entry((item.rule, item.origin)).or_default();
+                        if !spans.contains(&position) {
+                            spans.push(position);
+                        }
+                        let waiting: Vec<Item> = sets[item.origin]
+                            .iter()
+                            .filter(|parent| {
+                                let symbols = &grammar.rule(parent.rule).productions[parent.production].symbols;
+                                symbols.get(parent.dot) == Some(&Symbol::NonTerminal(item.rule))
+                            })
+                            .copied()
+                            .collect();
+                        for parent in waiting {
+                            add(position, Item { dot: parent.dot + 1, ..parent }, &mut sets);
+                        }
+                    }
+                    Some(&Symbol::NonTerminal(rule)) => {
+                        for production in 0..grammar.rule(rule).productions.len() {
+                            add(position, Item { rule, production, dot: 0, origin: position }, &mut sets);
+                        }
+                    }
+                    Some(&Symbol::Terminal(terminal)) => {
+                        if let Some(end) = match_terminal(&text, position, grammar.terminal(terminal)) {
+                            add(end, Item { dot: item.dot + 1, ..item }, &mut sets);
+                        }
+                    }
+                }
+            }
+        }
+
+        PhenotypeParse { grammar, text, completed, ends }
+    }
+
+    pub fn is_match(&self) -> bool {
+        self.ends.get(&(self.grammar.start(), 0)).is_some_and(|ends| ends.contains(&self.text.len()))
+    }
+
+    // Up to `limit` distinct derivations of the phenotype, each as the production indices
+    // chosen in the order the depth-first mapper expands non-terminals.
+    pub fn derivations(&self, limit: usize) -> Vec<Vec<usize>> {
+        let mut path = Vec::new();
+        self.derive(self.grammar.start(), 0, self.text.len(), limit, &mut path)
+    }
+
+    fn derive(&self, rule: usize, start: usize, end: usize, limit: usize, path: &mut Vec<(usize, usize, usize)>) -> Vec<Vec<usize>> {
+        // A rule deriving itself over the same span (e.g. `<a> ::= <a> | x`) would otherwise give
+        // infinitely many derivations.
+        if limit == 0 || path.contains(&(rule, start, end)) {
+            return Vec::new();
+        }
+        path.push((rule, start, end));
+        let mut derivations = Vec::new();
+        for (index, production) in self.grammar.rule(rule).productions.iter().enumerate() {
+            if !self.completed.contains(&(rule, index, start, end)) {
+                continue;
+            }
+            for choices in self.split(&production.symbols, start, end, limit - derivations.len(), path) {
+                derivations.push([vec![index], choices].concat());
+            }
+            if derivations.len() == limit {
+                break;
+            }
+        }
+        path.pop();
+        derivations
+    }
+
+    // Ways of deriving `symbols` over `start..end`.
+    fn split(&self, symbols: &[Symbol], start: usize, end: usize, limit: usize, path: &mut Vec<(usize, usize, usize)>) -> Vec<Vec<usize>> {
+        let Some((&first, rest)) = symbols.split_first() else {
+            return if start == end { vec![Vec::new()] } else { Vec::new() };
+        };
+        match first {
+            Symbol::Terminal(terminal) => match match_terminal(&self.text, start, self.grammar.terminal(terminal)) {
+                Some(next) if next <= end => self.split(rest, next, end, limit, path),
+                _ => Vec::new(),
+            },
+            Symbol::NonTerminal(rule) => {
+                let mut derivations = Vec::new();
+                for &middle in self.ends.get(&(rule, start)).into_iter().flatten() {
+                    if middle > end {
+                        continue;
+                    }
+                    let tails = self.split(rest, middle, end, limit, path);
+                    if tails.is_empty() {
+                        continue;
+                    }
+                    for head in self.derive(rule, start, middle, limit - derivations.len(), path) {
+                        for tail in &tails {
+                            derivations.push([head.clone(), tail.clone()].concat());
+                            if derivations.len() == limit {
+                                return derivations;
+                            }
+                        }
+                    }
+                }
+                derivations
+            }
+        }
+    }
+}
+
+// End of `terminal`, preceded by a space, if it occurs at `position`.
+fn match_terminal(text: &str, position: usize, terminal: &str) -> Option<usize> {
+    let rest = text.get(position..)?.strip_prefix(' ')?.strip_prefix(terminal)?;
+    Some(text.len() - rest.len())
+}
+
+// Genome that maps to exactly `phenotype` with the default mapper and no wrapping, or `None`
+// if the grammar cannot derive it. An ambiguous phenotype gets one of its derivations. Codons
+// are random values congruent to the chosen production modulo the rule's production count.
+pub fn reverse_map(grammar: &Grammar, phenotype: &str, rng: &mut impl Rng) -> Option<Genome> {
+    reverse_map_all(grammar, phenotype, 1, rng).pop()
+}
+
+// One genome for each of up to `limit` derivations of `phenotype`.
+pub fn reverse_map_all(grammar: &Grammar, phenotype: &str, limit: usize, rng: &mut impl Rng) -> Vec<Genome> {
+    let parse = PhenotypeParse::new(grammar, phenotype);
+    parse
+        .derivations(limit)
+        .into_iter()
+        .map(|choices| encode_choices(grammar, &choices, rng))
+        .collect()
+}
+
+// Encodes production indices chosen in depth-first order as codons.
+fn encode_choices(grammar: &Grammar, choices: &[usize], rng: &mut impl Rng) -> Genome {
+    let mut genome = Genome::with_capacity(choices.len());
+    let mut stack = vec![Symbol::NonTerminal(grammar.start())];
+    let mut choices = choices.iter();
+    while let Some(symbol) = stack.pop() {
+        if let Symbol::NonTerminal(rule) = symbol {
+            let productions = &grammar.rule(rule).productions;
+            let &production = choices.next().expect("a derivation chooses a production for every non-terminal");
+            genome.push(encode_choice(production, productions.len(), rng));
+            stack.extend(productions[production].symbols.iter().rev());
+        }
+    }
+    genome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::parse_grammar_str;
+    use crate::mapper::Mapper;
+    use crate::random;
+
+    fn map(grammar: &Grammar, genome: &[usize]) -> String {
+        let result = Mapper { max_wraps: 0, ..Mapper::default() }.map(grammar, genome);
+        assert!(result.is_valid());
+        assert_eq!(result.used_codons, genome.len());
+        result.phenotype
+    }
+
+    #[test]
+    fn test_reverse_map() {
+        let grammar = parse_grammar_str("<e> ::= <e> <op> <e> | ( <e> ) | <v>\n<op> ::= + | *\n<v> ::= x[0] | x[1] | 1.5").unwrap();
+        let mut rng = random::seeded(4);
+        for phenotype in ["x[0]", "( x[0] + 1.5 ) * x[1]", "( ( x[1] ) )"] {
+            let genome = reverse_map(&grammar, phenotype, &mut rng).unwrap();
+            assert_eq!(map(&grammar, &genome), phenotype);
+        }
+        assert!(reverse_map(&grammar, "x[0] +", &mut rng).is_none());
+        assert!(reverse_map(&grammar, "x[0]  + x[1]", &mut rng).is_none());
+        assert!(!PhenotypeParse::new(&grammar, "( x[0]").is_match());
+    }
+
+    #[test]
+    fn test_ambiguous_phenotype() {
+        let grammar = parse_grammar_str("<e> ::= <e> + <e> | x").unwrap();
+        let parse = PhenotypeParse::new(&grammar, "x + x + x");
+        assert!(parse.is_match());
+        let mut derivations = parse.derivations(10);
+        derivations.sort();
+        assert_eq!(derivations, vec![vec![0, 0, 1, 1, 1], vec![0, 1, 0, 1, 1]]);
+        assert_eq!(parse.derivations(1).len(), 1);
+
+        let genomes = reverse_map_all(&grammar, "x + x + x", 10, &mut random::seeded(5));
+        assert_eq!(genomes.len(), 2);
+        assert!(genomes.iter().all(|genome| map(&grammar, genome) == "x + x + x"));
+    }
+
+    #[test]
+    fn test_terminals_with_spaces_and_cycles() {
+        // `<a>` can derive itself, and `"a b"` can be read as one terminal or as `a` then `b`.
+        let grammar = parse_grammar_str("<s> ::= <a> | <a> <s>\n<a> ::= <a> | \"a b\" | a | b").unwrap();
+        let parse = PhenotypeParse::new(&grammar, "a b");
+        assert_eq!(parse.derivations(10).len(), 2);
+        for genome in reverse_map_all(&grammar, "a b", 10, &mut random::seeded(6)) {
+            assert_eq!(map(&grammar, &genome), "a b");
+        }
+    }
+}
@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+
+use rand::Rng;
+
+use crate::derivation::{Choice, DerivationTree};
+use crate::genome::Genome;
+use crate::grammar::{Grammar, GrammarError, Symbol};
+use crate::mapper::{partial_phenotype, InvalidReason, MappingResult, MappingStatus};
+
+// A production of an unrolled rule. Non-terminals index `SgeGrammar::rules`, terminals keep
+// the ids of the original grammar.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SgeProduction {
+    pub symbols: Vec<Symbol>,
+    // Index of the production in the original rule.
+    pub original: usize,
+}
+
+// A rule of the original grammar at one level of recursion.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SgeRule {
+    pub original: usize,
+    pub level: usize,
+    pub productions: Vec<SgeProduction>,
+    // Most times the rule can be expanded in one derivation, which is the length of its gene list.
+    pub max_expansions: usize,
+    // Position of the gene list in the genome.
+    pub offset: usize,
+}
+
+// Structured Grammatical Evolution (Lourenço et al.). Recursive rules are unrolled into one
+// copy per level of recursion, up to `max_depth` levels, and the genome holds one gene list
+// per unrolled rule with a gene for every time the rule can be expanded. Each gene directly
+// selects a production of its rule.
+//
+// The gene lists are stored back to back in a plain `Genome`, so SGE genomes have a fixed
+// length and go through the same crossover and evolution loop as classic GE genomes. The
+// engine uses SGE with `engine::Representation::Sge`.
+#[derive(Debug, Clone)]
+pub struct SgeGrammar {
+    grammar: Grammar,
+    rules: Vec<SgeRule>,
+    genome_length: usize,
+}
+
+impl SgeGrammar {
+    pub fn new(grammar: &Grammar, max_depth: usize) -> Result<SgeGrammar, GrammarError> {
+        let max_depth = max_depth.max(1);
+        let mut rules = vec![SgeRule {
+            original: grammar.start(),
+            level: 0,
+            productions: Vec::new(),
+            max_expansions: 0,
+            offset: 0,
+        }];
+        let mut ids: HashMap<(usize, usize), usize> = HashMap::from([((grammar.start(), 0), 0)]);
+        let mut pending = vec![0];
+
+        // A non-terminal from the same strongly connected component is one level deeper,
+        // anything else starts again at level 0. Productions that would need a level beyond
+        // `max_depth` are dropped.
+        while let Some(id) = pending.pop() {
+            let (original, level) = (rules[id].original, rules[id].level);
+            let rule = grammar.rule(original);
+            let mut productions = Vec::with_capacity(rule.productions.len());
+
+            'productions: for (index, production) in rule.productions.iter().enumerate() {
+                let mut symbols = Vec::with_capacity(production.symbols.len());
+                for &symbol in &production.symbols {
+                    let child = match symbol {
+                        Symbol::Terminal(_) => {
+                            symbols.push(symbol);
+                            continue;
+                        }
+                        Symbol::NonTerminal(child) => child,
+                    };
+                    let child_level = if grammar.rule(child).component == rule.component { level + 1 } else { 0 };
+                    if child_level >= max_depth {
+                        continue 'productions;
+                    }
+                    let child_id = *ids.entry((child, child_level)).or_insert_with(|| {
+                        rules.push(SgeRule {
+                            original: child,
+                            level: child_level,
+                            productions: Vec::new(),
+                            max_expansions: 0,
+                            offset: 0,
+                        });
+                        pending.push(rules.len() - 1);
+                        rules.len() - 1
+                    });
+                    symbols.push(Symbol::NonTerminal(child_id));
+                }
+                productions.push(SgeProduction { symbols, original: index });
+            }
+
+            rules[id].productions = productions;
+        }
+
+        // Dropping productions can leave a rule with none, which in turn makes every
+        // production that uses it unusable.
+        let mut changed = true;
+        while changed {
+            changed = false;
+            let empty: Vec<bool> = rules.iter().map(|rule| rule.productions.is_empty()).collect();
+            for rule in &mut rules {
+                let before = rule.productions.len();
+                rule.productions.retain(|production| {
+                    production.symbols.iter().all(|symbol| !matches!(symbol, Symbol::NonTerminal(id) if empty[*id]))
+                });
+                changed |= rule.productions.len() != before;
+            }
+        }
+        if rules[0].productions.is_empty() {
+            return Err(GrammarError::RecursionTooShallow(max_depth));
+        }
+
+        // The unrolled grammar is acyclic. Keep the rules that are still reachable, in
+        // topological order, so that expansion counts can be pushed down from the start symbol.
+        let order = topological_order(&rules);
+        let mut new_ids = vec![0; rules.len()];
+        for (new_id, &id) in order.iter().enumerate() {
+            new_ids[id] = new_id;
+        }
+        let mut rules: Vec<SgeRule> = order.iter().map(|&id| rules[id].clone()).collect();
+        for rule in &mut rules {
+            for production in &mut rule.productions {
+                for symbol in &mut production.symbols {
+                    if let Symbol::NonTerminal(id) = symbol {
+                        *id = new_ids[*id];
+                    }
+                }
+            }
+        }
+
+        rules[0].max_expansions = 1;
+        for id in 0..rules.len() {
+            let mut most_per_child: HashMap<usize, usize> = HashMap::new();
+            for production in &rules[id].productions {
+                let mut counts: HashMap<usize, usize> = HashMap::new();
+                for &symbol in &production.symbols {
+                    if let Symbol::NonTerminal(child) = symbol {
+                        *counts.entry(child).or_default() += 1;
+                    }
+                }
+                for (child, count) in counts {
+                    let most = most_per_child.entry(child).or_default();
+                    *most = (*most).max(count);
+                }
+            }
+            let expansions = rules[id].max_expansions;
+            for (child, most) in most_per_child {
+                rules[child].max_expansions += expansions * most;
+            }
+        }
+
+        let mut genome_length = 0;
+        for rule in &mut rules {
+            rule.offset = genome_length;
+            genome_length += rule.max_expansions;
+        }
+
+        Ok(SgeGrammar { grammar: grammar.clone(), rules, genome_length })
+    }
+
+    pub fn grammar(&self) -> &Grammar {
+        &self.grammar
+    }
+
+    // Unrolled rules; the start symbol is rule 0.
+    pub fn rules(&self) -> &[SgeRule] {
+        &self.rules
+    }
+
+    pub fn genome_length(&self) -> usize {
+        self.genome_length
+    }
+
+    pub fn gene_list<'a>(&self, genome: &'a [usize], rule: usize) -> &'a [usize] {
+        let rule = &self.rules[rule];
+        &genome[rule.offset..rule.offset + rule.max_expansions]
+    }
+
+    // Random genome with every gene a valid production index of its rule.
+    pub fn random_genome(&self, rng: &mut impl Rng) -> Genome {
+        let mut genome = Vec::with_capacity(self.genome_length);
+        for rule in &self.rules {
+            for _ in 0..rule.max_expansions {
+                genome.push(rng.gen_range(0..rule.productions.len()));
+            }
+        }
+        genome
+    }
+
+    pub fn initialize_population(&self, size: usize, rng: &mut impl Rng) -> Vec<Genome> {
+        (0..size).map(|_| self.random_genome(rng)).collect()
+    }
+
+    // Leftmost derivation where each rule reads the next gene of its own list. The result
+    // uses the original grammar's symbols and production indices, and `Choice::codon` is the
+    // gene's position in the genome. A genome of the right length is always valid.
+    pub fn map(&self, genome: &[usize], build_tree: bool) -> MappingResult {
+        self.expand(genome, build_tree).0
+    }
+
+    // Changes each gene that was used in the mapping to another production with the given
+    // probability. Rules with a single production are left alone.
+    pub fn mutate(&self, genome: &mut Genome, probability: f64, rng: &mut impl Rng) {
+        let (_, used) = self.expand(genome, false);
+
+        for (id, rule) in self.rules.iter().enumerate() {
+            let choices = rule.productions.len();
+            if choices < 2 {
+                continue;
+            }
+            for gene in &mut genome[rule.offset..rule.offset + used[id]] {
+                if rng.gen_bool(probability) {
+                    let value = rng.gen_range(0..choices - 1);
+                    *gene = if value >= *gene % choices { value + 1 } else { value };
+                }
+            }
+        }
+    }
+
+    fn original_symbol(&self, symbol: Symbol) -> Symbol {
+        match symbol {
+            Symbol::NonTerminal(id) => Symbol::NonTerminal(self.rules[id].original),
+            terminal => terminal,
+        }
+    }
+
+    // Maps the genome and also returns how many genes of each rule were used.
+    fn expand(&self, genome: &[usize], build_tree: bool) -> (MappingResult, Vec<usize>) {
+        let mut used = vec![0; self.rules.len()];
+        let mut tree = build_tree.then(|| DerivationTree::new(Symbol::NonTerminal(self.grammar.start())));
+        let mut output: Vec<&str> = Vec::new();
+        // Symbol of the unrolled grammar, depth and derivation tree node.
+        let mut stack = vec![(Symbol::NonTerminal(0), 1, 0)];
+        let mut max_depth = 0;
+        let mut status = MappingStatus::Valid;
+
+        while let Some((symbol, depth, node)) = stack.pop() {
+            let id = match symbol {
+                Symbol::Terminal(id) => {
+                    output.push(self.grammar.terminal(id));
+                    continue;
+                }
+                Symbol::NonTerminal(id) => id,
+            };
+
+            let rule = &self.rules[id];
+            let position = rule.offset + used[id];
+            if used[id] >= rule.max_expansions || position >= genome.len() {
+                status = MappingStatus::Invalid(InvalidReason::WrapLimit);
+                stack.push((symbol, depth, node));
+                break;
+            }
+            used[id] += 1;
+
+            let production = &rule.productions[genome[position] % rule.productions.len()];
+            max_depth = max_depth.max(depth);
+
+            match tree.as_mut() {
+                Some(tree) => {
+                    let symbols: Vec<Symbol> = production.symbols.iter().map(|&s| self.original_symbol(s)).collect();
+                    let choice = Choice { production: production.original, codon: position };
+                    let children = tree.expand(node, choice, &symbols);
+                    stack.extend(production.symbols.iter().zip(children).rev().map(|(&s, child)| (s, depth + 1, child)));
+                }
+                None => stack.extend(production.symbols.iter().rev().map(|&s| (s, depth + 1, 0))),
+            }
+        }
+
+        let pending = stack.iter().map(|&(symbol, _, _)| self.original_symbol(symbol));
+        let result = MappingResult {
+            phenotype: partial_phenotype(&self.grammar, &output, pending),
+            used_codons: used.iter().sum(),
+            wraps: 0,
+            max_depth,
+            status,
+            tree,
+        };
+        (result, used)
+    }
+}
+
+// Rules reachable from rule 0, each before any rule it uses.
+fn topological_order(rules: &[SgeRule]) -> Vec<usize> {
+    fn visit(rules: &[SgeRule], id: usize, visited: &mut Vec<bool>, order: &mut Vec<usize>) {
+        visited[id] = true;
+        for production in &rules[id].productions {
+            for &symbol in &production.symbols {
+                if let Symbol::NonTerminal(child) = symbol {
+                    if !visited[child] {
+                        visit(rules, child, visited, order);
+                    }
+                }
+            }
+        }
+        order.push(id);
+    }
+
+    let mut visited = vec![false; rules.len()];
+    let mut order = Vec::with_capacity(rules.len());
+    visit(rules, 0, &mut visited, &mut order);
+    order.reverse();
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::{parse_grammar_str, read_grammar_from_file};
+    use crate::random;
+
+    #[test]
+    fn test_unrolling() {
+        let grammar = parse_grammar_str("<e> ::= <e> + <e> | x").unwrap();
+        let sge = SgeGrammar::new(&grammar, 2).unwrap();
+        let levels: Vec<(usize, usize, usize)> =
+            sge.rules().iter().map(|rule| (rule.level, rule.productions.len(), rule.max_expansions)).collect();
+        assert_eq!(levels, vec![(0, 2, 1), (1, 1, 2)]);
+        assert_eq!(sge.genome_length(), 3);
+
+        assert_eq!(sge.map(&[0, 0, 0], false).phenotype, "x + x");
+        assert_eq!(sge.map(&[1, 0, 0], false).phenotype, "x");
+        assert_eq!(sge.gene_list(&[1, 5, 6], 1), &[5, 6]);
+
+        let result = sge.map(&[0, 0, 0], true);
+        assert!(result.is_valid());
+        assert_eq!(result.used_codons, 3);
+        let tree = result.tree.unwrap();
+        assert_eq!(tree.phenotype(&grammar), "x + x");
+        assert_eq!(tree.root().choice, Some(Choice { production: 0, codon: 0 }));
+        assert_eq!(tree.nodes[3].choice, Some(Choice { production: 1, codon: 2 }));
+    }
+
+    #[test]
+    fn test_mutual_recursion_depth() {
+        let grammar = parse_grammar_str("<e> ::= <f> | <f> + <e>\n<f> ::= ( <e> ) | x").unwrap();
+        assert!(matches!(SgeGrammar::new(&grammar, 1), Err(GrammarError::RecursionTooShallow(1))));
+
+        let sge = SgeGrammar::new(&grammar, 2).unwrap();
+        // <e> at level 0 can only use <f> at level 1, which can no longer recurse.
+        assert_eq!(sge.rules().len(), 2);
+        assert_eq!(sge.map(&[1, 0, 1], false).phenotype, "x");
+        assert_eq!(sge.map(&[0, 1], false).phenotype, "x");
+    }
+
+    #[test]
+    fn test_random_genomes_and_mutation() {
+        let path = format!("{}/grammars/vlad2.bnf", env!("CARGO_MANIFEST_DIR"));
+        let grammar = read_grammar_from_file(&path).unwrap();
+        let sge = SgeGrammar::new(&grammar, 4).unwrap();
+        let mut rng = random::seeded(3);
+
+        for mut genome in sge.initialize_population(50, &mut rng) {
+            assert_eq!(genome.len(), sge.genome_length());
+            let before = sge.map(&genome, false);
+            assert!(before.is_valid());
+
+            sge.mutate(&mut genome, 1.0, &mut rng);
+            let after = sge.map(&genome, false);
+            assert!(after.is_valid());
+            for (id, rule) in sge.rules().iter().enumerate() {
+                assert!(sge.gene_list(&genome, id).iter().all(|&gene| gene < rule.productions.len()));
+            }
+        }
+    }
+}